@@ -62,13 +62,13 @@ CODE_LIB = """// AUTOMATICALLY GENERATED: DO NOT EDIT!
 """
 CODE_PWM = """
 #[inline]
-pub(crate) fn pins_pwm(pin: &PinID) -> PwmID {{
+pub(crate) const fn pins_pwm(pin: &PinID) -> PwmID {{
     match pin {{
 {pins}}}
 }}"""
 CODE_I2C = """
 #[inline]
-pub(crate) fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {{
+pub(crate) const fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {{
     let d = match sda {{
 {i2c_sda}_ => return None,
     }};
@@ -79,7 +79,7 @@ pub(crate) fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {{
 }}"""
 CODE_SPI = """
 #[inline]
-pub(crate) fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&PinID>) -> Option<SpiID> {{
+pub(crate) const fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&PinID>) -> Option<SpiID> {{
     let d = match tx {{
 {spi_tx}_ => return None,
     }};
@@ -101,7 +101,7 @@ pub(crate) fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&
 }}"""
 CODE_UART = """
 #[inline]
-pub(crate) fn pins_uart(tx: &PinID, rx: &PinID, cts: Option<&PinID>, rts: Option<&PinID>) -> Option<UartID> {{
+pub(crate) const fn pins_uart(tx: &PinID, rx: &PinID, cts: Option<&PinID>, rts: Option<&PinID>) -> Option<UartID> {{
     let d = match tx {{
 {uart_tx}_ => return None,
     }};
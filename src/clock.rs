@@ -21,10 +21,12 @@
 
 extern crate core;
 
+use core::clone::Clone;
 use core::convert::{From, Into};
 use core::fmt::{self, Debug, Formatter};
-use core::marker::Sized;
+use core::marker::{Copy, Sized};
 use core::num::{NonZeroU8, NonZeroU16};
+use core::ops::BitOr;
 use core::option::Option::{self, None, Some};
 use core::result::Result;
 
@@ -34,10 +36,12 @@ use crate::time::{Month, Time, Weekday};
 mod alarm;
 mod rtc;
 mod sys;
+mod ticker;
 
 pub use self::alarm::*;
 pub use self::rtc::*;
 pub use self::sys::*;
+pub use self::ticker::*;
 
 pub enum RtcError {
     NotRunning,
@@ -56,6 +60,8 @@ pub struct AlarmConfig {
     pub weekday: Weekday,
 }
 
+pub struct AlarmMask(u8);
+
 pub trait TimeSource {
     type Error: Into<RtcError>;
 
@@ -112,6 +118,19 @@ impl AlarmConfig {
         self
     }
 
+    #[inline]
+    pub fn from_time(v: &Time, m: AlarmMask) -> AlarmConfig {
+        AlarmConfig {
+            day:     if m.contains(AlarmMask::DAY) { NonZeroU8::new(v.day) } else { None },
+            mins:    if m.contains(AlarmMask::MINS) { Some(v.mins) } else { None },
+            secs:    if m.contains(AlarmMask::SECS) { Some(v.secs) } else { None },
+            year:    if m.contains(AlarmMask::YEAR) { NonZeroU16::new(v.year) } else { None },
+            hours:   if m.contains(AlarmMask::HOURS) { Some(v.hours) } else { None },
+            month:   if m.contains(AlarmMask::MONTH) { v.month } else { Month::None },
+            weekday: if m.contains(AlarmMask::WEEKDAY) { v.weekday } else { Weekday::None },
+        }
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.day.is_none() && self.mins.is_none() && self.secs.is_none() && self.hours.is_none() && self.weekday.is_none()
@@ -122,6 +141,37 @@ impl AlarmConfig {
     }
 }
 
+impl AlarmMask {
+    pub const DAY: AlarmMask = AlarmMask(0x1u8);
+    pub const MINS: AlarmMask = AlarmMask(0x2u8);
+    pub const SECS: AlarmMask = AlarmMask(0x4u8);
+    pub const YEAR: AlarmMask = AlarmMask(0x8u8);
+    pub const HOURS: AlarmMask = AlarmMask(0x10u8);
+    pub const MONTH: AlarmMask = AlarmMask(0x20u8);
+    pub const WEEKDAY: AlarmMask = AlarmMask(0x40u8);
+
+    #[inline]
+    pub const fn contains(&self, m: AlarmMask) -> bool {
+        self.0 & m.0 == m.0
+    }
+}
+
+impl BitOr for AlarmMask {
+    type Output = AlarmMask;
+
+    #[inline]
+    fn bitor(self, rhs: AlarmMask) -> AlarmMask {
+        AlarmMask(self.0 | rhs.0)
+    }
+}
+impl Copy for AlarmMask {}
+impl Clone for AlarmMask {
+    #[inline]
+    fn clone(&self) -> AlarmMask {
+        *self
+    }
+}
+
 impl From<I2cError> for RtcError {
     #[inline]
     fn from(v: I2cError) -> RtcError {
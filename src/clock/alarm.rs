@@ -27,7 +27,7 @@ use core::marker::Copy;
 
 use cortex_m::interrupt::free;
 
-use crate::int::Acknowledge;
+use crate::int::{Acknowledge, Interrupt};
 use crate::pac::TIMER;
 use crate::{Board, write_reg};
 
@@ -69,6 +69,15 @@ impl Alarm {
         &self.i
     }
     #[inline]
+    pub fn irq(&self) -> Interrupt {
+        match &self.i {
+            AlarmID::Alarm0 => Interrupt::Alarm0,
+            AlarmID::Alarm1 => Interrupt::Alarm1,
+            AlarmID::Alarm2 => Interrupt::Alarm2,
+            AlarmID::Alarm3 => Interrupt::Alarm3,
+        }
+    }
+    #[inline]
     pub fn interrupt_clear(&mut self) {
         write_reg(self.dev.intf().as_ptr(), self.i as u32, true);
         match &self.i {
@@ -93,10 +102,13 @@ impl Alarm {
     }
     #[inline]
     pub fn schedule(&mut self, ms: u32) {
-        self.schedule_us(ms * 1_000);
+        self.schedule_us(ms.saturating_mul(1_000));
     }
     pub fn schedule_us(&mut self, us: u32) {
         let v = self.current_tick() + us as u64;
+        // NOTE(sf): The alarm only matches against the low 32 bits, so it'll fire
+        //           when 'timerawl' wraps around to this value. If we missed
+        //           it, we force it below.
         let l = (v & 0xFFFFFFFF) as u32;
         // Run without Interrupts
         free(|_| {
@@ -116,7 +128,8 @@ impl Alarm {
     }
     #[inline]
     pub fn interrupt_set(&mut self, en: bool) {
-        write_reg(self.dev.inte().as_ptr(), self.i as u32, !en)
+        write_reg(self.dev.inte().as_ptr(), self.i as u32, !en);
+        self.irq().set(en);
     }
 }
 
@@ -26,11 +26,15 @@ use core::option::Option::Some;
 use core::result::Result::{self, Err, Ok};
 
 use crate::asm::nop;
-use crate::clock::{AlarmConfig, RtcError, TimeSource};
+use crate::atomic::{Mutex, with};
+use crate::clock::{AlarmConfig, AlarmMask, RtcError, TimeSource};
 use crate::int::Acknowledge;
 use crate::pac::{RESETS, RTC};
 use crate::time::Time;
 
+// NOTE(sf): Period for 'set_periodic_interrupt' in seconds, zero when unused.
+static PERIOD: Mutex<u8> = Mutex::new(0u8);
+
 pub struct RtcClock {
     rtc: RTC,
 }
@@ -107,6 +111,7 @@ impl RtcClock {
         if !v.is_valid() {
             return Err(RtcError::InvalidTime);
         }
+        with(|x| *PERIOD.borrow_mut(x) = 0u8);
         self.wait_enable(false);
         self.rtc.irq_setup_0().write(|r| unsafe {
             if let Some(i) = v.day {
@@ -139,6 +144,35 @@ impl RtcClock {
         Ok(())
     }
     #[inline]
+    pub fn set_alarm_match(&self, v: Time, mask: AlarmMask) -> Result<(), RtcError> {
+        self.set_alarm(AlarmConfig::from_time(&v, mask))?;
+        self.interrupt_set(true);
+        Ok(())
+    }
+    pub fn set_periodic_interrupt(&self, seconds: u8) -> Result<(), RtcError> {
+        // NOTE(sf): The RTC alarm can only match whole fields, so the period has
+        //           a granularity of one second and must be less than a minute.
+        //           Each period matches on the next second value and is re-armed
+        //           by 'ack_interrupt'. Zero disables it.
+        if seconds >= 60 {
+            return Err(RtcError::InvalidTime);
+        }
+        if !self.is_running() {
+            return Err(RtcError::NotRunning);
+        }
+        with(|x| {
+            *PERIOD.borrow_mut(x) = seconds;
+            if seconds == 0 {
+                self.wait_enable(false);
+                self.interrupt_set(false);
+            } else {
+                self.periodic_arm(seconds);
+                self.interrupt_set(true);
+            }
+        });
+        Ok(())
+    }
+    #[inline]
     pub fn set_time_from(&self, mut v: impl TimeSource) -> Result<(), RtcError> {
         self.set_time(v.now().map_err(|e| e.into())?)
     }
@@ -150,6 +184,17 @@ impl RtcClock {
             nop();
         }
     }
+    fn periodic_arm(&self, n: u8) {
+        // NOTE(sf): With no fields enabled the match would hold continuously,
+        //           so even a one second period matches on the next second.
+        self.wait_enable(false);
+        let s = (self.rtc.rtc_0().read().sec().bits() + n) % 60;
+        self.rtc.irq_setup_0().write(|r| r);
+        self.rtc
+            .irq_setup_1()
+            .write(|r| unsafe { r.sec_ena().set_bit().sec().bits(s) });
+        self.wait_enable(true);
+    }
     fn now_inner(&self) -> Result<Time, RtcError> {
         if !self.is_running() {
             return Err(RtcError::NotRunning);
@@ -187,7 +232,11 @@ impl TimeSource for &RtcClock {
 impl Acknowledge for RtcClock {
     #[inline]
     fn ack_interrupt(&mut self) -> bool {
-        self.interrupt_clear();
-        true
+        let r = self.rtc.ints().read().rtc().bit_is_set();
+        with(|x| match *PERIOD.borrow(x) {
+            0 => self.interrupt_clear(),
+            n => self.periodic_arm(n),
+        });
+        r
     }
 }
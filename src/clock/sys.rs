@@ -24,14 +24,18 @@ extern crate core;
 use core::clone::Clone;
 use core::hint::unreachable_unchecked;
 use core::iter::Iterator;
+use core::marker::Copy;
 use core::mem::zeroed;
 use core::ops::AddAssign;
-use core::option::Option::{None, Some};
+use core::option::Option::{self, None, Some};
+use core::ptr::write_volatile;
 use core::unreachable;
 
 use crate::asm::{delay, nop};
 use crate::clock::RtcClock;
 use crate::pac::{CLOCKS, PLL_SYS, PLL_USB, RESETS, ROSC, RTC, SCB, SYST, TIMER, XOSC};
+use crate::pin::{PinFunction, PinID};
+use crate::sys::{Voltage, voltage};
 
 pub(crate) const DIV: u32 = 0x100u32;
 
@@ -39,15 +43,50 @@ const FREQ_RTC: u32 = 46_875u32;
 const FREQ_XOSC: u32 = 12_000_000u32;
 const FREQ_ROSC: u32 = 149_500_000u32;
 
+const PLL_VCO_MIN: u32 = 750_000_000u32;
+const PLL_VCO_MAX: u32 = 1_600_000_000u32;
+// NOTE(sf): Anything above the rated 133MHz needs the core voltage raised
+//           above the default 1.10V first.
+const PLL_VOLTAGE_FREQ: u32 = 133_000_000u32;
+
+#[repr(u8)]
+pub enum GpoutSource {
+    PllSys = 0x0u8,
+    PllUsb = 0x3u8,
+    Rosc   = 0x4u8,
+    Xosc   = 0x5u8,
+    Sys    = 0x6u8,
+    Usb    = 0x7u8,
+    Adc    = 0x8u8,
+    Rtc    = 0x9u8,
+    Ref    = 0xAu8,
+}
+
+#[repr(u8)]
+pub enum FcSource {
+    PllSys = 0x1u8,
+    PllUsb = 0x2u8,
+    Rosc   = 0x3u8,
+    Xosc   = 0x5u8,
+    Ref    = 0x8u8,
+    Sys    = 0x9u8,
+    Peri   = 0xAu8,
+    Usb    = 0xBu8,
+    Adc    = 0xCu8,
+    Rtc    = 0xDu8,
+}
+
 pub struct Timer {
     clk:  SYST,
     int:  TIMER,
     freq: u32,
 }
 pub struct Clock {
-    rtc:  RtcClock,
-    freq: u32,
-    seed: u32,
+    rtc:   RtcClock,
+    freq:  u32,
+    seed:  u32,
+    pfreq: u32,
+    rfreq: u32,
 }
 
 impl Clock {
@@ -66,9 +105,9 @@ impl Clock {
         // Setup the internal clocks.
         setup_ref(&c, false);
         setup_sys(&c);
-        setup_per(&c);
+        setup_per(&c, false);
         // TODO(sf): Correct clock skew
-        let r = setup_rtc(&c, (f as f32 * 1f32) as u32, FREQ_RTC + 125);
+        let r = setup_rtc(&c, 0x2, (f as f32 * 1f32) as u32, FREQ_RTC + 125);
         // Enable the RTC and ROSC to go DORMANT
         c.sleep_en0().write(|r| unsafe { r.bits(0x300000) });
         c.sleep_en1().write(|r| unsafe { r.bits(0) });
@@ -77,13 +116,80 @@ impl Clock {
         while x.status().read().stable().bit_is_set() || x.ctrl().read().enable().is_enable() {
             nop();
         }
-        setup_powersave(&c); // Disable the unused clocks.
+        setup_powersave(&c, false); // Disable the unused clocks.
         Clock {
-            rtc:  RtcClock::new(r),
-            freq: f,
-            seed: t,
+            rtc:   RtcClock::new(r),
+            freq:  f,
+            seed:  t,
+            pfreq: f,
+            rfreq: f,
+        }
+    }
+    pub(crate) fn new_xosc_peri(xosc_ref: bool) -> Clock {
+        let c = unsafe { CLOCKS::steal() }; // Disable Resus
+        unsafe { c.clk_sys_resus_ctrl().write_with_zero(|w| w) };
+        // Setup XOSC and keep it running as it's the clk_peri source.
+        setup_xosc();
+        // Setup and tune the ROSC.
+        let (f, t) = setup_rosc(&c, FREQ_ROSC);
+        // Setup the internal clocks.
+        setup_ref(&c, xosc_ref);
+        setup_sys(&c);
+        setup_per(&c, true);
+        // NOTE(sf): The XOSC is running anyway, so we use it for the RTC as it
+        //           divides evenly down to the RTC frequency.
+        let r = setup_rtc(&c, 0x3, FREQ_XOSC, FREQ_RTC + 1);
+        // NOTE(sf): DORMANT is not available in this mode as it would stop the
+        //           XOSC under the peripherals.
+        c.sleep_en0().write(|r| unsafe { r.bits(0x300000) });
+        c.sleep_en1().write(|r| unsafe { r.bits(0) });
+        setup_powersave(&c, false);
+        Clock {
+            rtc:   RtcClock::new(r),
+            freq:  f,
+            seed:  t,
+            pfreq: FREQ_XOSC,
+            rfreq: if xosc_ref { FREQ_XOSC } else { f },
         }
     }
+    pub(crate) fn new_pll(freq: u32) -> Option<Clock> {
+        if freq > PLL_VOLTAGE_FREQ && voltage() as u8 <= Voltage::Volts1_10 as u8 {
+            return None;
+        }
+        let (b, d1, d2) = pll_calc(freq)?;
+        let c = unsafe { CLOCKS::steal() }; // Disable Resus
+        unsafe { c.clk_sys_resus_ctrl().write_with_zero(|w| w) };
+        // Setup XOSC and set it as the reference clock. We keep it running as
+        // it's the PLL source.
+        setup_xosc();
+        setup_ref(&c, true);
+        // Move clk_sys to clk_ref while we reprogram the PLL.
+        c.clk_sys_ctrl().modify(|_, r| r.src().clear_bit());
+        while c.clk_sys_selected().read().bits() != 0x1 {
+            nop();
+        }
+        setup_pll(b, d1, d2);
+        c.clk_sys_div().modify(|_, r| unsafe { r.bits(DIV) });
+        c.clk_sys_ctrl().modify(|_, r| unsafe { r.auxsrc().bits(0) });
+        c.clk_sys_ctrl().modify(|_, r| r.src().set_bit());
+        while c.clk_sys_selected().read().bits() != 0x2 {
+            nop();
+        }
+        setup_per(&c, false);
+        // NOTE(sf): The RTC runs from the XOSC here, which divides evenly down
+        //           to the RTC frequency, so no skew correction is needed.
+        let r = setup_rtc(&c, 0x3, FREQ_XOSC, FREQ_RTC + 1);
+        // Use the free-running ROSC as the seed source.
+        let t = rosc_read(&c);
+        setup_powersave(&c, true);
+        Some(Clock {
+            freq,
+            rtc: RtcClock::new(r),
+            seed: t,
+            pfreq: freq,
+            rfreq: FREQ_XOSC,
+        })
+    }
 
     #[inline]
     pub fn freq(&self) -> u32 {
@@ -94,14 +200,48 @@ impl Clock {
         self.seed
     }
     #[inline]
+    pub fn peri_freq(&self) -> u32 {
+        self.pfreq
+    }
+    #[inline]
     pub fn rtc(&self) -> &RtcClock {
         &self.rtc
     }
     #[inline]
+    pub fn measure_frequency(&self, src: FcSource) -> u32 {
+        let c = unsafe { CLOCKS::steal() };
+        fc0_read(&c, self.rfreq / 1_000, src as u8)
+    }
+    pub fn gpout(&self, n: u8, pin: PinID, src: GpoutSource, div_int: u16, div_frac: u8) -> bool {
+        // NOTE(sf): GPOUT0-3 are only routable to GPIO21, 23, 24 and 25. We
+        //           check by number as not every board exposes these pins.
+        match (n, pin as u8) {
+            (0, 21) | (1, 23) | (2, 24) | (3, 25) => (),
+            _ => return false,
+        }
+        let c = unsafe { CLOCKS::steal() };
+        // NOTE(sf): The four GPOUT generators share the same layout and are
+        //           laid out back to back as CTRL, DIV, SELECTED.
+        let r = unsafe { c.clk_gpout0_ctrl().as_ptr().add(n as usize * 3) };
+        unsafe {
+            write_volatile(r, 0);
+            write_volatile(r.add(1), (div_int as u32).unchecked_shl(8) | div_frac as u32);
+            write_volatile(r, 0x800 | (src as u32).unchecked_shl(5));
+        }
+        pin.set_output();
+        pin.set_function(PinFunction::Clock);
+        true
+    }
+    #[inline]
     pub fn set_wake_only_with_enabled(&self, en: bool) {
         // 0x10 - SEVONPEND
         unsafe { (&*SCB::PTR).scr.modify(|r| if en { r | 0x10 } else { r & !0x10 }) }
     }
+
+    #[inline]
+    pub(crate) fn ref_freq(&self) -> u32 {
+        self.rfreq
+    }
 }
 impl Timer {
     #[inline]
@@ -134,6 +274,14 @@ impl Timer {
         self.sleep_us(m * 1000);
     }
     pub fn sleep_us(&self, v: u32) {
+        // 0x2 - TICKINT, SysTick belongs to a Ticker, so use the TIMER instead.
+        if self.clk.csr.read() & 0x2 != 0 {
+            let d = self.current_tick() + v as u64;
+            while self.current_tick() < d {
+                nop();
+            }
+            return;
+        }
         let t = (v as u64) * ((self.freq as u64) / 1_000_000);
         let c = unsafe { t.unchecked_shr(24) };
         if c > 0 {
@@ -162,6 +310,38 @@ impl Timer {
         }
         unsafe { self.clk.csr.modify(|r| r & !0x1) }
     }
+    pub fn recalibrate(&mut self) -> u32 {
+        // NOTE(sf): When running from the ROSC, the reference clock is the ROSC
+        //           too, so FC0 can't tell us anything. We bring up the XOSC and
+        //           use it as the reference for the measurement, which means
+        //           the TIMER and watchdog ticks will be off until it's done.
+        //
+        //           If the XOSC is already running (it can be the clk_peri
+        //           source), it's only borrowed and left running after.
+        let (c, v) = unsafe { (CLOCKS::steal(), XOSC::steal()) };
+        let x = c.clk_ref_selected().read().bits() == 0x4;
+        let e = v.ctrl().read().enable().is_enable() && v.status().read().stable().bit_is_set();
+        if !x {
+            if !e {
+                setup_xosc();
+            }
+            setup_ref(&c, true);
+        }
+        let f = fc0_read(&c, FREQ_XOSC / 1_000, FcSource::Sys as u8);
+        if !x {
+            setup_ref(&c, false);
+        }
+        if !x && !e {
+            unsafe { v.ctrl().write_with_zero(|r| r.enable().disable()) };
+            while v.status().read().stable().bit_is_set() || v.ctrl().read().enable().is_enable() {
+                nop();
+            }
+        }
+        if f > 0 {
+            self.freq = f;
+        }
+        self.freq
+    }
     pub fn current_tick(&self) -> u64 {
         let mut v = self.int.timerawh().read().bits();
         loop {
@@ -177,6 +357,21 @@ impl Timer {
     }
 }
 
+impl Copy for FcSource {}
+impl Clone for FcSource {
+    #[inline]
+    fn clone(&self) -> FcSource {
+        *self
+    }
+}
+impl Copy for GpoutSource {}
+impl Clone for GpoutSource {
+    #[inline]
+    fn clone(&self) -> GpoutSource {
+        *self
+    }
+}
+
 impl Clone for Timer {
     #[inline]
     fn clone(&self) -> Timer {
@@ -205,13 +400,32 @@ fn setup_xosc() -> XOSC {
     v
 }
 #[inline]
+fn setup_pll(fb: u16, d1: u8, d2: u8) {
+    let (p, r) = (unsafe { PLL_SYS::steal() }, unsafe { RESETS::steal() });
+    r.reset().modify(|_, r| r.pll_sys().set_bit());
+    r.reset().modify(|_, r| r.pll_sys().clear_bit());
+    while r.reset_done().read().pll_sys().bit_is_clear() {
+        nop();
+    }
+    p.cs().write(|r| unsafe { r.refdiv().bits(1) });
+    p.fbdiv_int().write(|r| unsafe { r.fbdiv_int().bits(fb) });
+    // Power up the VCO and wait for it to lock.
+    p.pwr().modify(|_, r| r.pd().clear_bit().vcopd().clear_bit());
+    while p.cs().read().lock().bit_is_clear() {
+        nop();
+    }
+    p.prim()
+        .write(|r| unsafe { r.postdiv1().bits(d1).postdiv2().bits(d2) });
+    p.pwr().modify(|_, r| r.postdivpd().clear_bit());
+}
+#[inline]
 fn rosc_reset(rosc: &ROSC) {
     rosc_set_div(rosc, 1);
     rosc.ctrl().write(|r| unsafe { r.freq_range().bits(0xFA4) });
     rosc_write_freq(rosc, &[0, 0, 0, 0, 0, 0, 0, 0]);
 }
 #[inline]
-fn setup_per(clocks: &CLOCKS) {
+fn setup_per(clocks: &CLOCKS, xosc: bool) {
     clocks.clk_peri_ctrl().modify(|_, r| r.enable().clear_bit());
     while clocks.clk_peri_ctrl().read().enable().bit_is_set() {
         nop();
@@ -219,7 +433,7 @@ fn setup_per(clocks: &CLOCKS) {
     delay(100);
     clocks
         .clk_peri_ctrl()
-        .modify(|_, r| unsafe { r.auxsrc().bits(0).enable().set_bit() });
+        .modify(|_, r| unsafe { r.auxsrc().bits(if xosc { 0x4 } else { 0 }).enable().set_bit() });
     while clocks.clk_peri_ctrl().read().enable().bit_is_clear() {
         nop();
     }
@@ -292,7 +506,7 @@ fn rosc_range(rosc: &ROSC) -> bool {
     true
 }
 #[inline]
-fn setup_powersave(clocks: &CLOCKS) {
+fn setup_powersave(clocks: &CLOCKS, pll: bool) {
     clocks.clk_usb_ctrl().modify(|_, r| r.enable().clear_bit());
     clocks.clk_adc_ctrl().modify(|_, r| r.enable().clear_bit());
     clocks.clk_gpout0_ctrl().modify(|_, r| r.enable().clear_bit());
@@ -304,23 +518,29 @@ fn setup_powersave(clocks: &CLOCKS) {
     u.cs().write(|r| r.bypass().set_bit());
     u.pwr()
         .write(|r| r.pd().set_bit().dsmpd().set_bit().postdivpd().set_bit().vcopd().set_bit());
-    let p = unsafe { PLL_SYS::steal() };
-    p.cs().write(|r| r.bypass().set_bit());
-    p.pwr()
-        .write(|r| r.pd().set_bit().dsmpd().set_bit().postdivpd().set_bit().vcopd().set_bit());
+    if !pll {
+        let p = unsafe { PLL_SYS::steal() };
+        p.cs().write(|r| r.bypass().set_bit());
+        p.pwr()
+            .write(|r| r.pd().set_bit().dsmpd().set_bit().postdivpd().set_bit().vcopd().set_bit());
+    }
     // Enable DEEP sleep.
     unsafe { (&*SCB::PTR).scr.modify(|r| r | 0x4) }
 }
 #[inline]
 fn rosc_read(clocks: &CLOCKS) -> u32 {
+    fc0_read(clocks, FREQ_XOSC / 1_000, 0x3)
+}
+#[inline]
+fn fc0_read(clocks: &CLOCKS, ref_khz: u32, src: u8) -> u32 {
     while clocks.fc0_status().read().running().bit_is_set() {
         nop();
     }
-    clocks.fc0_ref_khz().write(|r| unsafe { r.fc0_ref_khz().bits(0x2EE0) });
+    clocks.fc0_ref_khz().write(|r| unsafe { r.fc0_ref_khz().bits(ref_khz) });
     clocks.fc0_interval().write(|r| unsafe { r.fc0_interval().bits(0xA) });
     clocks.fc0_min_khz().write(|r| unsafe { r.fc0_min_khz().bits(0) });
     clocks.fc0_max_khz().write(|r| unsafe { r.fc0_max_khz().bits(0x1FFFFFF) });
-    clocks.fc0_src().write(|r| unsafe { r.fc0_src().bits(0x3) });
+    clocks.fc0_src().write(|r| unsafe { r.fc0_src().bits(src) });
     while clocks.fc0_status().read().done().bit_is_clear() {
         nop();
     }
@@ -402,7 +622,7 @@ fn setup_rosc(clocks: &CLOCKS, freq: u32) -> (u32, u32) {
     // Tune the ROSC to get a good freqency value.
     rosc_tune(&v, clocks, freq)
 }
-fn setup_rtc(clocks: &CLOCKS, clk_freq: u32, freq: u32) -> RTC {
+fn setup_rtc(clocks: &CLOCKS, src: u8, clk_freq: u32, freq: u32) -> RTC {
     // BUG(sf): RTC clock skews a bit after a period of time in a linear path.
     //          This is potentially due to the system clock frequency?
     let f = ((clk_freq as f32 / (FREQ_RTC as f32)) * 100f32) as u32;
@@ -415,7 +635,7 @@ fn setup_rtc(clocks: &CLOCKS, clk_freq: u32, freq: u32) -> RTC {
         nop();
     }
     delay(((clk_freq / freq) + 1) * 3);
-    clocks.clk_rtc_ctrl().modify(|_, r| unsafe { r.auxsrc().bits(0x2) });
+    clocks.clk_rtc_ctrl().modify(|_, r| unsafe { r.auxsrc().bits(src) });
     clocks.clk_rtc_div().modify(|_, r| unsafe { r.bits(d) });
     clocks.clk_rtc_ctrl().modify(|_, r| r.enable().set_bit());
     while clocks.clk_rtc_ctrl().read().enable().bit_is_clear() {
@@ -480,3 +700,47 @@ fn rosc_tune(rosc: &ROSC, clocks: &CLOCKS, target: u32) -> (u32, u32) {
     }
     (m, t)
 }
+fn pll_calc(freq: u32) -> Option<(u16, u8, u8)> {
+    // NOTE(sf): Search from the highest VCO frequency down as it has the lowest
+    //           jitter. Only exact matches are accepted as the whole point of
+    //           using the PLL is a deterministic system clock.
+    for b in (16..=320u32).rev() {
+        let v = FREQ_XOSC * b;
+        if v < PLL_VCO_MIN || v > PLL_VCO_MAX {
+            continue;
+        }
+        for d1 in 1..=7u32 {
+            for d2 in 1..=d1 {
+                if freq.checked_mul(d1 * d2) == Some(v) {
+                    return Some((b as u16, d1 as u8, d2 as u8));
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(feature = "embedded-hal")]
+mod hal {
+    extern crate core;
+    extern crate embedded_hal;
+
+    use self::embedded_hal::delay::DelayNs;
+    use crate::clock::Timer;
+
+    impl DelayNs for Timer {
+        #[inline]
+        fn delay_ns(&mut self, ns: u32) {
+            // Round up, a short delay is worse than a long one.
+            self.sleep_us(ns.div_ceil(1_000))
+        }
+        #[inline]
+        fn delay_us(&mut self, us: u32) {
+            self.sleep_us(us)
+        }
+        #[inline]
+        fn delay_ms(&mut self, ms: u32) {
+            self.sleep_ms(ms)
+        }
+    }
+}
@@ -0,0 +1,94 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::cmp::Ord;
+use core::mem::zeroed;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::Board;
+use crate::int::{Acknowledge, wait_for_interrupt};
+use crate::pac::SYST;
+
+static TICKS: AtomicU32 = AtomicU32::new(0u32);
+
+// NOTE(sf): The Ticker takes over SysTick, so a SysTick handler needs to be
+//           set before creating it, either with 'InterruptHandler::set_systick'
+//           and 'ticker_handler' or with an '#[exception]' that calls 'tick'.
+//           While it's running, the Timer sleep functions will use the TIMER
+//           instead of SysTick.
+pub struct Ticker(SYST);
+
+impl Ticker {
+    #[inline]
+    pub fn new(p: &Board) -> Ticker {
+        let v: SYST = unsafe { zeroed() };
+        unsafe {
+            v.csr.write(0x4);
+            v.rvr.write(((p.system_freq() / 1_000).saturating_sub(1)).min(0xFFFFFF));
+            v.cvr.write(0);
+            // 0x4 - CLKSOURCE (processor), 0x2 - TICKINT, 0x1 - ENABLE
+            v.csr.write(0x7);
+        }
+        Ticker(v)
+    }
+
+    #[inline]
+    pub fn tick() {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn stop(self) {
+        unsafe { self.0.csr.write(0x4) }
+    }
+    #[inline]
+    pub fn millis(&self) -> u32 {
+        TICKS.load(Ordering::Relaxed)
+    }
+    pub fn delay_ms(&self, ms: u32) {
+        let s = self.millis();
+        while self.millis().wrapping_sub(s) < ms {
+            wait_for_interrupt();
+        }
+    }
+}
+
+impl Acknowledge for Ticker {
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        // Reading CSR clears COUNTFLAG.
+        if self.0.csr.read() & 0x10000 == 0 {
+            return false;
+        }
+        Ticker::tick();
+        true
+    }
+}
+
+#[inline]
+pub fn millis() -> u32 {
+    TICKS.load(Ordering::Relaxed)
+}
+pub extern "C" fn ticker_handler() {
+    Ticker::tick()
+}
@@ -26,17 +26,20 @@ use core::clone::Clone;
 use core::fmt::{self, Debug, Formatter};
 use core::marker::{Copy, Sync};
 use core::matches;
-use core::mem::{ManuallyDrop, drop, zeroed};
+use core::iter::Iterator;
+use core::mem::{ManuallyDrop, drop, size_of, zeroed};
 use core::ops::FnOnce;
 use core::result::Result::{self, Err, Ok};
 use core::sync::atomic::{Ordering, compiler_fence};
 
-use crate::asm::{nop, sev, udf};
+use crate::asm::{nop, sev, udf, wfe};
 use crate::atomic::{Mutex, with};
 use crate::fifo::Fifo;
 use crate::pac::{MPU, PPB, PSM, RESETS, SIO, SYST};
 use crate::static_instance;
 
+pub const CORE1_FAULT: u32 = 0xFA17C0DEu32;
+
 const ATTEMPTS: u8 = 0x8u8;
 
 static_instance!(CORE1_STATE, CoreState, CoreState::Uninit);
@@ -59,6 +62,7 @@ pub struct CoreStack<const N: usize = 2048>(UnsafeCell<[usize; N]>);
 enum CoreState {
     Uninit,
     Active,
+    Faulted,
     Available,
 }
 
@@ -74,10 +78,29 @@ impl Core {
     }
 }
 impl<const N: usize> CoreStack<N> {
+    const PAINT: usize = 0xCCCCCCCCusize;
+
     #[inline]
     pub const fn new() -> CoreStack<N> {
         CoreStack(UnsafeCell::new([0usize; N]))
     }
+
+    #[inline]
+    pub fn paint(&self) {
+        // NOTE(sf): Only call this before the stack is given to 'spawn', as it
+        //           overwrites the whole stack.
+        for i in unsafe { &mut *self.0.get() }.iter_mut() {
+            *i = Self::PAINT;
+        }
+    }
+    #[inline]
+    pub fn high_water(&self) -> usize {
+        // The stack grows down, so the first word that's not painted (from the
+        // base) is the deepest the stack has gone.
+        let v = unsafe { &*self.0.get() };
+        let n = v.iter().position(|i| *i != Self::PAINT).unwrap_or(N);
+        (N - n) * size_of::<usize>()
+    }
 }
 
 impl Copy for Core {}
@@ -124,6 +147,28 @@ pub fn is_running(core: Core) -> bool {
     }
 }
 #[inline]
+pub fn core1_fault() -> bool {
+    matches!(core1_get_status(), CoreState::Faulted)
+}
+pub fn fault() -> ! {
+    // NOTE(sf): This is meant to be called from the user's '#[panic_handler]'
+    //           (and HardFault handler, as the core1 stack guard 'udf's). On
+    //           core1 it marks the core as faulted and sends 'CORE1_FAULT'
+    //           through the FIFO so core0 can react, either by polling
+    //           'core1_fault' or by watching the 'Sio0' interrupt. Core1 then
+    //           parks itself until it is respawned.
+    //
+    //           On core0 there's nobody to report to, so we just fault.
+    if matches!(Core::current(), Core::C0) {
+        udf();
+    }
+    core1_status(CoreState::Faulted);
+    let _ = Fifo::get().write(CORE1_FAULT);
+    loop {
+        wfe();
+    }
+}
+#[inline]
 pub fn interrupt(core: Core) -> Result<(), CoreError> {
     match core {
         Core::C0 => Err(CoreError::InvalidCore),
@@ -146,7 +191,7 @@ pub fn spawn<const N: usize, F: FnOnce() -> () + Sync>(core: Core, stack: &'stat
     match core1_get_status() {
         CoreState::Available => return core1_push(func),
         CoreState::Active => return Err(CoreError::InUse),
-        CoreState::Uninit => (),
+        CoreState::Uninit | CoreState::Faulted => (),
     }
     let mut f = Fifo::get();
     let x = unsafe { &mut *stack.0.get() };
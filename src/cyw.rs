@@ -22,13 +22,16 @@
 
 extern crate core;
 
+use core::clone::Clone;
+use core::cmp::Ord;
 use core::result::Result::{self, Err, Ok};
 
 use crate::Board;
+use crate::clock::Timer;
 use crate::cyw::device::Device;
 use crate::pin::{PinDirection, PinID, PinState};
 use crate::pio::state::Stopped;
-use crate::pio::{Config, Pio, PioID, Program, Shift, Slot, State};
+use crate::pio::{Config, Pio, PioAllocator, PioID, Program, Shift, Slot, State};
 
 mod data;
 mod device;
@@ -41,11 +44,29 @@ pub enum CywError {
     NoBluetooth,
     InitFailure,
     InvalidFrequency,
+    InvalidArgument,
+    Timeout,
+    IoctlFailure,
+    NotFound,
+    AuthFailure,
+    JoinFailure,
+    InUse,
+    Underflow,
+    Overflow,
+    CommandError,
+    DataNotAvailable,
 }
 
 pub struct Cyw43 {
     dev: Device,
 }
+pub struct ScanResult {
+    ssid:     [u8; 32],
+    rssi:     i16,
+    bssid:    [u8; 6],
+    ssid_len: u8,
+    channel:  u8,
+}
 
 impl Cyw43 {
     pub fn new(p: &Board) -> Result<Cyw43, CywError> {
@@ -73,8 +94,19 @@ impl Cyw43 {
             0x20A0, //  6: wait   1 pin, 0   side 0
             0xC000, //  7: irq    nowait 0   side 0
         ]);
-        let mut v = Pio::get(p, PioID::Pio0);
-        let i = v.install(&c).or(Err(CywError::Code))?;
+        // NOTE(sf): We take the StateMachine directly, so claim it first to
+        //           make sure nobody else has it.
+        if !PioAllocator::claim_slot(PioID::Pio0, Slot::Index0) {
+            return Err(CywError::InUse);
+        }
+        let v = Pio::get(p, PioID::Pio0);
+        let i = match v.install(&c) {
+            Ok(h) => h,
+            Err(_) => {
+                PioAllocator::release(PioID::Pio0, Slot::Index0);
+                return Err(CywError::Code);
+            },
+        };
         let mut s = Config::new_program(&i)
             .sideset_pin(PinID::Pin29)
             .output_pin(PinID::Pin24)
@@ -98,7 +130,81 @@ impl Cyw43 {
         }
     }
 
+    #[inline]
+    pub fn set_led(&mut self, on: bool) {
+        self.dev.set_led(on)
+    }
+    #[inline]
+    pub fn load_firmware(&mut self, fw: &[u8], clm: &[u8]) -> Result<(), CywError> {
+        self.dev.load_firmware(fw, data::fw::CONFIG, clm)
+    }
+    #[inline]
+    pub fn set_gpio(&mut self, n: u8, on: bool) {
+        self.dev.set_gpio(n, on)
+    }
+    #[inline]
+    pub fn join_wpa2(&mut self, ssid: &str, pass: &str, timeout: &Timer) -> Result<(), CywError> {
+        self.dev.join_wpa2(ssid, pass, timeout)
+    }
+    #[inline]
+    pub fn scan(&mut self, results: &mut [ScanResult]) -> Result<usize, CywError> {
+        self.dev.scan(results)
+    }
+
     pub fn init(&mut self, bluetooth: bool) -> Result<(), CywError> {
         Ok(())
     }
 }
+impl ScanResult {
+    #[inline]
+    pub const fn empty() -> ScanResult {
+        ScanResult {
+            ssid:     [0u8; 32],
+            rssi:     0i16,
+            bssid:    [0u8; 6],
+            ssid_len: 0u8,
+            channel:  0u8,
+        }
+    }
+
+    #[inline]
+    pub fn rssi(&self) -> i16 {
+        self.rssi
+    }
+    #[inline]
+    pub fn ssid(&self) -> &[u8] {
+        &self.ssid[0..(self.ssid_len as usize).min(32)]
+    }
+    #[inline]
+    pub fn bssid(&self) -> &[u8; 6] {
+        &self.bssid
+    }
+    #[inline]
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    #[inline]
+    pub(crate) fn from_bss(b: &[u8]) -> ScanResult {
+        let mut r = ScanResult::empty();
+        r.bssid.copy_from_slice(&b[8..14]);
+        r.ssid_len = b[18].min(32);
+        r.ssid.copy_from_slice(&b[19..51]);
+        r.channel = b[72];
+        r.rssi = i16::from_le_bytes([b[78], b[79]]);
+        r
+    }
+}
+
+impl Clone for ScanResult {
+    #[inline]
+    fn clone(&self) -> ScanResult {
+        ScanResult {
+            ssid:     self.ssid,
+            rssi:     self.rssi,
+            bssid:    self.bssid,
+            ssid_len: self.ssid_len,
+            channel:  self.channel,
+        }
+    }
+}
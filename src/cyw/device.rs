@@ -25,17 +25,30 @@ extern crate core;
 use core::clone::Clone;
 use core::cmp::Ord;
 use core::fmt::Write;
+use core::iter::Iterator;
+use core::ops::FnMut;
+use core::option::Option::{self, None, Some};
 use core::result::Result::{self, Err, Ok};
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 
 use crate::Board;
 use crate::clock::Timer;
-use crate::cyw::CywError;
+use crate::cyw::{CywError, ScanResult};
 use crate::pin::gpio::Output;
 use crate::pin::{Pin, PinDirection, PinID, PinState};
 use crate::pio::state::{Running, Stopped};
 use crate::pio::{Config, Machine, Pio, PioID, Program, Rx, Shift, Slot, State, Tx};
 
+const RAM_SIZE: u32 = 0x80000u32;
+const CORE_WLAN: u32 = 0x18103000u32;
+const CORE_SOCSRAM: u32 = 0x18104000u32;
+const TIMEOUT: u64 = 500_000u64;
+const TIMEOUT_SCAN: u64 = 10_000_000u64;
+const TIMEOUT_JOIN: u64 = 10_000_000u64;
+
+// SDPCM (12) + CDC (16) headers.
+const HEADER: usize = 0x1Cusize;
+
 pub struct Device {
     t:      Timer,
     sm:     Machine<Running>,
@@ -46,6 +59,9 @@ pub struct Device {
     pwr:    Pin<Output>,
     offset: u8,
     status: u32,
+    id:     u16,
+    seq:    u8,
+    credit: u8,
 }
 
 impl Device {
@@ -62,6 +78,9 @@ impl Device {
             tx: m.tx_u32(),
             pwr: Pin::get(&p, PinID::Pin23).output(false),
             status: 0u32,
+            id: 0u16,
+            seq: 0u8,
+            credit: 1u8,
         }
     }
 
@@ -99,6 +118,184 @@ impl Device {
         self.write16(0, 0x6, 0xBE | if bt { 0x2000 } else { 0 });
         Ok(())
     }
+    pub fn load_firmware(&mut self, fw: &[u8], nvram: &[u8], clm: &[u8]) -> Result<(), CywError> {
+        // Request the ALP clock and wait for it.
+        self.write8(1, 0x1000E, 0x8);
+        self.write8(1, 0x10008, 0x10);
+        self.wait(|d| d.read8(1, 0x1000E) & 0x40 != 0)?;
+        self.write8(1, 0x1000E, 0);
+        self.core_disable(CORE_WLAN);
+        self.core_disable(CORE_SOCSRAM);
+        self.core_reset(CORE_SOCSRAM);
+        // Disable remap for SRAM_3.
+        self.write_bp32(0x18004010, 0x3);
+        self.write_bp32(0x18004044, 0);
+        self.write_bp_bytes(0, fw);
+        // NOTE(sf): NVRAM goes at the end of RAM, followed by it's size in words
+        //           and the inverse size in the top half.
+        let n = (nvram.len() as u32 + 3) & !3;
+        self.write_bp_bytes(RAM_SIZE - 4 - n, nvram);
+        self.write_bp32(RAM_SIZE - 4, ((!(n / 4)) << 0x10) | (n / 4));
+        self.core_reset(CORE_WLAN);
+        if !self.core_is_up(CORE_WLAN) {
+            return Err(CywError::InitFailure);
+        }
+        // Wait for the HT clock.
+        self.wait(|d| d.read8(1, 0x1000E) & 0x80 != 0)?;
+        self.write_bp32(0x18002024, 0xF0);
+        self.write16(0, 0x6, 0x20);
+        self.write8(1, 0x10008, 0x20);
+        // Wait for F2 to be ready, this means the firmware is up.
+        self.wait(|d| d.read32(0, 0x8) & 0x20 != 0)?;
+        self.write8(1, 0x1000F, 0);
+        self.write8(1, 0x1000E, 0x10);
+        self.wait(|d| d.read8(1, 0x1000E) & 0x80 != 0)?;
+        self.load_clm(clm)
+    }
+    pub fn join_wpa2(&mut self, ssid: &str, pass: &str, timeout: &Timer) -> Result<(), CywError> {
+        if ssid.is_empty() || ssid.len() > 32 || pass.len() < 8 || pass.len() > 64 {
+            return Err(CywError::InvalidArgument);
+        }
+        self.up()?;
+        self.set_iovar("ampdu_ba_wsize", &8u32.to_le_bytes())?;
+        // WSEC AES
+        self.set_ioctl(0x86, &4u32.to_le_bytes())?;
+        self.set_iovar("bsscfg:sup_wpa", &[0, 0, 0, 0, 1, 0, 0, 0])?;
+        self.set_iovar("bsscfg:sup_wpa2_eapver", &[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF])?;
+        self.set_iovar("bsscfg:sup_wpa_tmo", &[0, 0, 0, 0, 0xC4, 0x09, 0, 0])?;
+        self.t.sleep_ms(100);
+        // NOTE(sf): The passphrase is given to the chip and it does the PSK
+        //           derivation itself.
+        let mut b = [0u8; 0x44];
+        b[0..2].copy_from_slice(&(pass.len() as u16).to_le_bytes());
+        b[2] = 1;
+        b[4..4 + pass.len()].copy_from_slice(pass.as_bytes());
+        self.set_ioctl(0x10C, &b)?;
+        // Infrastructure mode, open auth with WPA2 PSK.
+        self.set_ioctl(0x14, &1u32.to_le_bytes())?;
+        self.set_ioctl(0x16, &0u32.to_le_bytes())?;
+        self.set_ioctl(0xA5, &0x80u32.to_le_bytes())?;
+        let mut v = [0u8; 0x24];
+        v[0..4].copy_from_slice(&(ssid.len() as u32).to_le_bytes());
+        v[4..4 + ssid.len()].copy_from_slice(ssid.as_bytes());
+        self.set_ioctl(0x1A, &v)?;
+        let mut w = [0u32; 0x200];
+        // NOTE(sf): The AP can take a few seconds to finish the 4-way
+        //           handshake, so this uses the same window as scanning.
+        let d = timeout.current_tick() + TIMEOUT_JOIN;
+        loop {
+            if timeout.current_tick() > d {
+                return Err(CywError::Timeout);
+            }
+            let r = self.recv(&mut w);
+            let (t, v) = match event(&bytes(&w)[0..r]) {
+                Some((t, v, _)) => (t, v),
+                None => continue,
+            };
+            match t {
+                // SET_SSID, status 3 is 'no networks'.
+                0x0 if v == 3 => return Err(CywError::NotFound),
+                0x0 if v != 0 => return Err(CywError::JoinFailure),
+                // AUTH, DEAUTH_IND and DISASSOC_IND
+                0x3 if v != 0 => return Err(CywError::AuthFailure),
+                0x6 | 0xC => return Err(CywError::AuthFailure),
+                // PSK_SUP, status 6 is 'keyed', which means we're done.
+                0x2E if v == 6 => return Ok(()),
+                0x2E if v != 0 => return Err(CywError::AuthFailure),
+                _ => (),
+            }
+        }
+    }
+    pub fn scan(&mut self, results: &mut [ScanResult]) -> Result<usize, CywError> {
+        self.up()?;
+        // Escan params, version 1, start action, any BSS type, active scan and
+        // default timings for everything else.
+        let mut p = [0u8; 0x4A];
+        p[0] = 1;
+        p[4] = 1;
+        p[6] = 1;
+        p[0x2C..0x32].fill(0xFF);
+        p[0x32] = 2;
+        p[0x34..0x44].fill(0xFF);
+        self.set_iovar("escan", &p)?;
+        let (mut w, mut n) = ([0u32; 0x200], 0usize);
+        let d = self.t.current_tick() + TIMEOUT_SCAN;
+        loop {
+            if self.t.current_tick() > d {
+                return Err(CywError::Timeout);
+            }
+            let r = self.recv(&mut w);
+            let (t, v, e) = match event(&bytes(&w)[0..r]) {
+                Some(x) => x,
+                None => continue,
+            };
+            if t != 0x45 {
+                continue;
+            }
+            // Status 8 is a partial result, there's more coming.
+            if v != 8 {
+                break;
+            }
+            // Skip the escan result header to get to the BSS info.
+            if e.len() < 0xC + 0x50 || n >= results.len() {
+                continue;
+            }
+            let x = ScanResult::from_bss(&e[0xC..]);
+            if results[0..n].iter().any(|i| i.bssid() == x.bssid()) {
+                continue;
+            }
+            results[n] = x;
+            n += 1;
+        }
+        Ok(n)
+    }
+    #[inline]
+    pub fn set_iovar(&mut self, name: &str, v: &[u8]) -> Result<(), CywError> {
+        self.ioctl(2, 0x107, name, v, &mut []).map(|_| ())
+    }
+    #[inline]
+    pub fn set_ioctl(&mut self, cmd: u32, v: &[u8]) -> Result<(), CywError> {
+        self.ioctl(2, cmd, "", v, &mut []).map(|_| ())
+    }
+    #[inline]
+    pub fn get_iovar(&mut self, name: &str, out: &mut [u8]) -> Result<usize, CywError> {
+        self.ioctl(0, 0x106, name, &[], out)
+    }
+    #[inline]
+    pub fn get_ioctl(&mut self, cmd: u32, out: &mut [u8]) -> Result<usize, CywError> {
+        self.ioctl(0, cmd, "", &[], out)
+    }
+    #[inline]
+    pub fn set_led(&mut self, on: bool) {
+        self.set_gpio(0, on)
+    }
+    pub fn set_gpio(&mut self, n: u8, on: bool) {
+        // NOTE(sf): These are the ChipCommon 'gpioout' and 'gpioouten' registers,
+        //           we can drive them over the backplane directly.
+        let m = 1u32 << (n & 0x1F);
+        let e = self.read_bp32(0x18000068);
+        if e & m == 0 {
+            self.write_bp32(0x18000068, e | m);
+        }
+        let v = self.read_bp32(0x18000064);
+        self.write_bp32(0x18000064, if on { v | m } else { v & !m });
+    }
+    pub fn set_bus_clock(&mut self, sys_freq: u32, target_hz: u32) -> Result<(), CywError> {
+        // NOTE(sf): Divisor in 8.8 fixed point, 'target_hz' is the PIO clock
+        //           and each gSPI bit takes two cycles of it.
+        if target_hz == 0 || target_hz > sys_freq {
+            return Err(CywError::InvalidFrequency);
+        }
+        let d = ((sys_freq as u64 * 0x100) / target_hz as u64).min(0xFFFFFF) as u32;
+        let e = self.sm.is_enabled();
+        self.sm.set_state(false);
+        self.sm.set_clock_div((d >> 8) as u16, d as u8);
+        self.sm.restart_clock();
+        if e {
+            self.sm.set_state(true);
+        }
+        Ok(())
+    }
     #[inline]
     pub fn bp_set_window(&mut self, v: u32) {
         let n = v & !0x7FFF;
@@ -182,7 +379,7 @@ impl Device {
                 from_raw_parts_mut(w.as_mut_ptr() as *mut u8, 0x40)[0..n].copy_from_slice(&b[i..i + n]);
             }
             self.cmd_write(
-                0xE0000000 | ((o & 0x1FFFF) << 0xB) | n as u32,
+                0xD0000000 | ((o & 0x1FFFF) << 0xB) | n as u32,
                 &w[0..(n + 3) / 4],
             );
             a = a.saturating_add(n as u32);
@@ -215,7 +412,7 @@ impl Device {
             let n = s.saturating_sub(i).min(0x40).min(0x8000usize - o as usize);
             self.bp_set_window(a);
             self.cmd_read(
-                0x60000000 | ((o & 0x1FFFF) << 0xB) | n as u32,
+                0x50000000 | ((o & 0x1FFFF) << 0xB) | n as u32,
                 &mut w[0..(n + 3) / 4 + 1],
             );
             unsafe {
@@ -258,7 +455,175 @@ impl Device {
             &[v],
         )
     }
+    #[inline]
+    pub fn status(&self) -> u32 {
+        self.status
+    }
+    #[inline]
+    pub fn check_status(&self) -> Result<(), CywError> {
+        // NOTE(sf): Status word returned after every gSPI command.
+        //           0x01 - F2/F3 read data not available.
+        //           0x02 - F2/F3 FIFO underflow (read more than available).
+        //           0x04 - F2/F3 FIFO overflow (wrote more than space).
+        //           0x80 - Command/data error on the last transaction.
+        match self.status {
+            v if v & 0x80 != 0 => Err(CywError::CommandError),
+            v if v & 0x4 != 0 => Err(CywError::Overflow),
+            v if v & 0x2 != 0 => Err(CywError::Underflow),
+            v if v & 0x1 != 0 => Err(CywError::DataNotAvailable),
+            _ => Ok(()),
+        }
+    }
+    #[inline]
+    pub fn try_read(&mut self, func: u32, addr: u32, len: u32) -> Result<u32, CywError> {
+        let v = self.read(func, addr, len);
+        self.check_status()?;
+        Ok(v)
+    }
+    #[inline]
+    pub fn try_write(&mut self, func: u32, addr: u32, len: u32, v: u32) -> Result<(), CywError> {
+        self.write(func, addr, len, v);
+        self.check_status()
+    }
+
+    pub(super) fn recv(&mut self, w: &mut [u32]) -> usize {
+        let s = self.read32(0, 0x8);
+        if s & 0x100 == 0 {
+            return 0;
+        }
+        let n = (((s & 0xFFE00) >> 9) as usize).min(w.len() * 4);
+        self.read_wlan(n as u32, w);
+        let b = bytes(w);
+        if n < 0xC || (b[0] as u16 | (b[1] as u16) << 8) ^ (b[2] as u16 | (b[3] as u16) << 8) != 0xFFFF {
+            return 0;
+        }
+        if b[5] & 0xF < 3 {
+            // NOTE(sf): The chip can report a bogus window after a reset, don't
+            //           trust anything more than 0x40 ahead of us.
+            self.credit = if b[9].wrapping_sub(self.seq) > 0x40 { self.seq.wrapping_add(2) } else { b[9] };
+        }
+        n
+    }
+    pub(super) fn ioctl(
+        &mut self,
+        kind: u32,
+        cmd: u32,
+        name: &str,
+        v: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, CywError> {
+        let mut w = [0u32; 0x200];
+        // Iovar names are NUL terminated.
+        let k = HEADER + name.len() + if name.is_empty() { 0 } else { 1 };
+        let n = k + v.len().max(out.len());
+        if n > w.len() * 4 {
+            return Err(CywError::IoctlFailure);
+        }
+        // NOTE(sf): Wait for the chip to give us credit to send. Anything we read
+        //           while waiting is dropped.
+        self.wait(|d| {
+            d.seq != d.credit && d.credit.wrapping_sub(d.seq) & 0x80 == 0 || {
+                d.recv(&mut w);
+                false
+            }
+        })?;
+        w.fill(0);
+        self.id = self.id.wrapping_add(1);
+        let b = bytes_mut(&mut w);
+        b[0..2].copy_from_slice(&(n as u16).to_le_bytes());
+        b[2..4].copy_from_slice(&(!(n as u16)).to_le_bytes());
+        b[4] = self.seq;
+        b[7] = 0xC;
+        b[0xC..0x10].copy_from_slice(&cmd.to_le_bytes());
+        b[0x10..0x14].copy_from_slice(&((n - HEADER) as u32).to_le_bytes());
+        b[0x14..0x18].copy_from_slice(&(kind | (self.id as u32) << 0x10).to_le_bytes());
+        b[HEADER..HEADER + name.len()].copy_from_slice(name.as_bytes());
+        b[k..k + v.len()].copy_from_slice(v);
+        self.seq = self.seq.wrapping_add(1);
+        self.write_wlan(&w[0..(n + 3) / 4]);
+        let d = self.t.current_tick() + TIMEOUT;
+        loop {
+            if self.t.current_tick() > d {
+                return Err(CywError::Timeout);
+            }
+            let r = self.recv(&mut w);
+            let b = bytes(&w);
+            if r == 0 || b[5] & 0xF != 0 {
+                continue;
+            }
+            let h = b[7] as usize;
+            if r < h + 0x10 || (b[h + 0xA] as u16 | (b[h + 0xB] as u16) << 8) != self.id {
+                continue;
+            }
+            if b[h + 0xC..h + 0x10] != [0, 0, 0, 0] {
+                return Err(CywError::IoctlFailure);
+            }
+            let c = (r - h - 0x10).min(out.len());
+            out[0..c].copy_from_slice(&b[h + 0x10..h + 0x10 + c]);
+            return Ok(c);
+        }
+    }
 
+    fn up(&mut self) -> Result<(), CywError> {
+        // Enable all events on the primary interface.
+        let mut m = [0xFFu8; 0x1C];
+        m[0..4].fill(0);
+        self.set_iovar("bsscfg:event_msgs", &m)?;
+        self.set_ioctl(0x2, &[])
+    }
+    fn load_clm(&mut self, clm: &[u8]) -> Result<(), CywError> {
+        let mut b = [0u8; 0x40C];
+        let n = clm.len().div_ceil(0x400);
+        for (i, v) in clm.chunks(0x400).enumerate() {
+            // Handler version flag, plus begin/end flags.
+            let f = 0x1000u16 | if i == 0 { 0x2 } else { 0 } | if i + 1 == n { 0x4 } else { 0 };
+            b[0..2].copy_from_slice(&f.to_le_bytes());
+            b[2..4].copy_from_slice(&2u16.to_le_bytes());
+            b[4..8].copy_from_slice(&(v.len() as u32).to_le_bytes());
+            b[8..0xC].fill(0);
+            b[0xC..0xC + v.len()].copy_from_slice(v);
+            self.set_iovar("clmload", &b[0..0xC + v.len()])?;
+        }
+        let mut s = [0u8; 4];
+        self.get_iovar("clmload_status", &mut s)?;
+        if u32::from_le_bytes(s) != 0 {
+            return Err(CywError::InitFailure);
+        }
+        Ok(())
+    }
+    fn core_reset(&mut self, base: u32) {
+        self.core_disable(base);
+        self.write_bp8(base + 0x408, 0x3);
+        let _ = self.read_bp8(base + 0x408);
+        self.write_bp8(base + 0x800, 0);
+        self.t.sleep_ms(1);
+        self.write_bp8(base + 0x408, 0x1);
+        let _ = self.read_bp8(base + 0x408);
+        self.t.sleep_ms(1);
+    }
+    fn core_is_up(&mut self, base: u32) -> bool {
+        self.read_bp8(base + 0x408) & 0x3 == 0x1 && self.read_bp8(base + 0x800) & 0x1 == 0
+    }
+    fn core_disable(&mut self, base: u32) {
+        let _ = self.read_bp8(base + 0x800);
+        if self.read_bp8(base + 0x800) & 0x1 != 0 {
+            return;
+        }
+        self.write_bp8(base + 0x408, 0);
+        let _ = self.read_bp8(base + 0x408);
+        self.t.sleep_ms(1);
+        self.write_bp8(base + 0x800, 0x1);
+        let _ = self.read_bp8(base + 0x800);
+    }
+    fn wait(&mut self, mut f: impl FnMut(&mut Device) -> bool) -> Result<(), CywError> {
+        let d = self.t.current_tick() + TIMEOUT;
+        while !f(self) {
+            if self.t.current_tick() > d {
+                return Err(CywError::Timeout);
+            }
+        }
+        Ok(())
+    }
     #[inline]
     fn prepare(&mut self, r: u32, w: u32) {
         self.sm.set_state(false);
@@ -296,3 +661,32 @@ impl Device {
 fn word(op: bool, inc: bool, f: u32, a: u32, n: u32) -> u32 {
     (if op { 1 } else { 0 } << 0x1F) | (if inc { 1 } else { 0 } << 0x1E) | (f) << 0x1C | ((a & 0x1FFFF) << 0xB) | (n)
 }
+// NOTE(sf): Returns the event type, status and event data from an SDPCM event
+//           packet. Everything past the SDPCM header is big-endian.
+fn event(b: &[u8]) -> Option<(u32, u32, &[u8])> {
+    if b.len() < 0xC || b[5] & 0xF != 1 {
+        return None;
+    }
+    // SDPCM header, then BDC header and padding, then the Ethernet (14) and
+    // Broadcom (10) event headers.
+    let h = b[7] as usize;
+    if b.len() < h + 4 {
+        return None;
+    }
+    let e = h + 4 + b[h + 3] as usize * 4 + 0x18;
+    if b.len() < e + 0x30 {
+        return None;
+    }
+    let t = u32::from_be_bytes([b[e + 4], b[e + 5], b[e + 6], b[e + 7]]);
+    let s = u32::from_be_bytes([b[e + 8], b[e + 9], b[e + 0xA], b[e + 0xB]]);
+    let n = u32::from_be_bytes([b[e + 0x14], b[e + 0x15], b[e + 0x16], b[e + 0x17]]) as usize;
+    Some((t, s, &b[e + 0x30..(e + 0x30 + n).min(b.len())]))
+}
+#[inline]
+pub(super) fn bytes(w: &[u32]) -> &[u8] {
+    unsafe { from_raw_parts(w.as_ptr() as *const u8, w.len() * 4) }
+}
+#[inline]
+fn bytes_mut(w: &mut [u32]) -> &mut [u8] {
+    unsafe { from_raw_parts_mut(w.as_mut_ptr() as *mut u8, w.len() * 4) }
+}
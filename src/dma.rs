@@ -23,15 +23,21 @@ extern crate core;
 
 use core::clone::Clone;
 use core::cmp::Ord;
+use core::convert::From;
 use core::marker::{Copy, PhantomData};
-use core::mem::size_of;
-use core::option::Option;
+use core::mem::{ManuallyDrop, size_of, transmute};
+use core::ops::Drop;
+use core::option::Option::{self, None, Some};
+use core::ptr::read;
+use core::result::Result::{self, Err, Ok};
 use core::sync::atomic::{Ordering, compiler_fence};
 
 use crate::asm::{dsb, nop};
+use crate::atomic::{Mutex, with};
 use crate::dma::mode::{BiDirection, Double, DoubleUp, Single};
 use crate::pac::DMA;
 use crate::pac::dma::CH;
+use crate::write_reg;
 
 #[repr(u8)]
 pub enum Dma {
@@ -51,13 +57,36 @@ pub enum Dma {
 pub enum DmaPace {
     Sink,
     Source,
+    Timer(u8),
+}
+#[repr(u8)]
+pub enum SniffMode {
+    Crc32         = 0x0u8,
+    Crc32Reversed = 0x1u8,
+    Crc16         = 0x2u8,
+    Crc16Reversed = 0x3u8,
+    Parity        = 0xEu8,
+    Sum           = 0xFu8,
 }
 
+pub struct DmaPool;
+pub struct DmaMem<'a, T: DmaWord>(&'a [T]);
+pub struct DmaMemMut<'a, T: DmaWord>(&'a mut [T]);
 pub struct DmaConfig<D: DmaDirection>(D);
 pub struct DmaStream<D: DmaDirection>(D);
 
+struct Setup<'a> {
+    pace:  &'a DmaPace,
+    swap:  bool,
+    ring:  u8,
+    sniff: bool,
+    start: bool,
+}
+
 pub trait DmaWord {}
-pub trait DmaDirection {}
+pub trait DmaDirection {
+    fn channels(&self) -> (Dma, Option<Dma>);
+}
 pub trait DmaReader<T: DmaWord> {
     fn rx_req(&self) -> Option<u8>;
     fn rx_info(&self) -> (u32, u32);
@@ -74,7 +103,57 @@ pub type DmaSingle<T, R, W> = DmaConfig<Single<T, R, W>>;
 pub type DmaDouble<T, R, W> = DmaConfig<Double<T, R, W>>;
 pub type DmaBiDirection<T, R, W, B> = DmaConfig<BiDirection<T, R, W, B>>;
 
+// NOTE(sf): Bitmask of the channels handed out by 'DmaPool', lower 12 bits
+//           map to 'Chan0' through 'Chan11'.
+static POOL: Mutex<u16> = Mutex::new(0u16);
+
+impl DmaPool {
+    pub fn claim() -> Option<Dma> {
+        with(|x| {
+            let u = POOL.borrow_mut(x);
+            for i in 0..12u8 {
+                let m = unsafe { 1u16.unchecked_shl(i as u32) };
+                if *u & m != 0 {
+                    continue;
+                }
+                *u |= m;
+                return Some(unsafe { transmute::<u8, Dma>(i) });
+            }
+            None
+        })
+    }
+    #[inline]
+    pub fn release(ch: Dma) {
+        with(|x| *POOL.borrow_mut(x) &= !unsafe { 1u16.unchecked_shl(ch as u32) })
+    }
+    #[inline]
+    pub fn is_claimed(ch: Dma) -> bool {
+        with(|x| *POOL.borrow(x) & unsafe { 1u16.unchecked_shl(ch as u32) } != 0)
+    }
+    pub fn claim_chan(ch: Dma) -> Option<Dma> {
+        with(|x| {
+            let (u, m) = (POOL.borrow_mut(x), unsafe { 1u16.unchecked_shl(ch as u32) });
+            if *u & m != 0 {
+                return None;
+            }
+            *u |= m;
+            Some(ch)
+        })
+    }
+}
 impl Dma {
+    #[inline]
+    pub fn set_irq(&self, which: u8, en: bool) {
+        // NOTE(sf): 'which' selects INTE0 (Dma0) or INTE1 (Dma1), the NVIC
+        //           interrupt still needs to be enabled by the handler.
+        let d = unsafe { DMA::steal() };
+        write_reg(
+            if which == 0 { d.inte0().as_ptr() } else { d.inte1().as_ptr() },
+            unsafe { 1u32.unchecked_shl(*self as u32) },
+            !en,
+        )
+    }
+
     #[inline]
     fn start(&self) {
         unsafe {
@@ -124,10 +203,41 @@ impl Dma {
         d.ints1().write(|r| unsafe { r.bits(1u32.unchecked_shl(*self as u32)) });
         true
     }
-    fn setup<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>>(&self, from: &R, to: &W, swap: bool, pace: &DmaPace, start: bool) {
-        let v = match pace {
+    #[inline]
+    fn abort(&self, other: Option<Dma>) {
+        let m = unsafe { 1u32.unchecked_shl(*self as u32) | other.map_or(0, |v| 1u32.unchecked_shl(v as u32)) };
+        // NOTE(sf): Clear the enable bits first so an abort can't kick off any
+        //           chained channel before we're done.
+        self.ptr().ch_al1_ctrl().modify(|_, r| r.en().clear_bit());
+        if let Some(v) = other {
+            v.ptr().ch_al1_ctrl().modify(|_, r| r.en().clear_bit());
+        }
+        let d = unsafe { DMA::steal() };
+        d.chan_abort().write(|r| unsafe { r.bits(m) });
+        while d.chan_abort().read().bits() & m != 0 {
+            nop();
+        }
+        while self.ptr().ch_ctrl_trig().read().busy().bit_is_set() {
+            nop();
+        }
+        if let Some(v) = other {
+            while v.ptr().ch_ctrl_trig().read().busy().bit_is_set() {
+                nop();
+            }
+        }
+    }
+    #[inline]
+    fn sniff(&self, m: SniffMode, seed: u32) {
+        let d = unsafe { DMA::steal() };
+        d.sniff_data().write(|r| unsafe { r.bits(seed) });
+        d.sniff_ctrl()
+            .write(|r| unsafe { r.dmach().bits(*self as u8).calc().bits(m as u8).en().set_bit() });
+    }
+    fn setup<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>>(&self, from: &R, to: &W, c: Setup) -> u32 {
+        let v = match c.pace {
             DmaPace::Source => from.rx_req().or_else(|| to.tx_req()).unwrap_or(0x3F),
             DmaPace::Sink => to.tx_req().or_else(|| from.rx_req()).unwrap_or(0x3F),
+            DmaPace::Timer(t) => 0x3B + (t & 0x3),
         };
         let (j, k) = from.rx_info();
         let (y, u) = to.tx_info();
@@ -142,19 +252,43 @@ impl Dma {
                 .treq_sel()
                 .bits(v)
                 .bswap()
-                .bit(swap)
+                .bit(c.swap)
+                .sniff_en()
+                .bit(c.sniff)
+                .ring_sel()
+                .bit(c.ring & 0x10 != 0)
+                .ring_size()
+                .bits(c.ring & 0xF)
                 .chain_to()
                 .bits(*self as u8)
                 .en()
                 .bit(true)
         });
+        let n = k.min(u);
         d.ch_read_addr().write(|r| unsafe { r.bits(j) });
-        d.ch_trans_count().write(|r| unsafe { r.bits(k.min(u)) });
-        if start {
+        d.ch_trans_count().write(|r| unsafe { r.bits(n) });
+        if c.start {
             d.ch_al2_write_addr_trig().write(|r| unsafe { r.bits(y) });
         } else {
             d.ch_write_addr().write(|r| unsafe { r.bits(y) });
         }
+        n
+    }
+}
+impl DmaPace {
+    #[inline]
+    pub fn timer(timer: u8, num: u16, den: u16) -> DmaPace {
+        // NOTE(sf): The timer fires at sys_freq * (num / den), 'num' must be
+        //           less than or equal to 'den' or the rate is undefined.
+        let v = unsafe { (num.min(den) as u32).unchecked_shl(16) | den as u32 };
+        let d = unsafe { DMA::steal() };
+        match timer & 0x3 {
+            0 => d.timer0().write(|r| unsafe { r.bits(v) }),
+            1 => d.timer1().write(|r| unsafe { r.bits(v) }),
+            2 => d.timer2().write(|r| unsafe { r.bits(v) }),
+            _ => d.timer3().write(|r| unsafe { r.bits(v) }),
+        };
+        DmaPace::Timer(timer & 0x3)
     }
 }
 impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaConfig<Single<T, R, W>> {
@@ -166,6 +300,10 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaConfig<Single<T, R, W>> {
             ch_from: from,
             pace: DmaPace::Source,
             swap: false,
+            ring: 0u8,
+            seed: 0u32,
+            count: 0u32,
+            sniff: None,
             _p: PhantomData,
         })
     }
@@ -175,19 +313,54 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaConfig<Single<T, R, W>> {
         self.0.pace = v
     }
     #[inline]
+    pub fn pace_timer(&mut self, timer: u8, num: u16, den: u16) {
+        self.0.pace = DmaPace::timer(timer, num, den)
+    }
+    #[inline]
+    pub fn sniff(&mut self, m: SniffMode) {
+        self.0.sniff = Some(m)
+    }
+    #[inline]
     pub fn bit_swap(&mut self, swap: bool) {
         self.0.swap = swap
     }
     #[inline]
-    pub fn start(self) -> DmaStream<Single<T, R, W>> {
+    pub fn ring_read(&mut self, size_log2: u8) -> bool {
+        if !is_ring_aligned(self.0.ch_from.rx_info().0, size_log2) {
+            return false;
+        }
+        self.0.ring = size_log2;
+        true
+    }
+    #[inline]
+    pub fn sniff_seed(&mut self, v: u32) {
+        self.0.seed = v
+    }
+    #[inline]
+    pub fn ring_write(&mut self, size_log2: u8) -> bool {
+        if !is_ring_aligned(self.0.ch_to.tx_info().0, size_log2) {
+            return false;
+        }
+        self.0.ring = if size_log2 == 0 { 0 } else { size_log2 | 0x10 };
+        true
+    }
+    #[inline]
+    pub fn start(mut self) -> DmaStream<Single<T, R, W>> {
         dsb();
         compiler_fence(Ordering::SeqCst);
-        self.0.ch.setup(
+        if let Some(m) = &self.0.sniff {
+            self.0.ch.sniff(*m, self.0.seed);
+        }
+        self.0.count = self.0.ch.setup(
             &self.0.ch_from,
             &self.0.ch_to,
-            self.0.swap,
-            &self.0.pace,
-            true,
+            Setup {
+                pace:  &self.0.pace,
+                swap:  self.0.swap,
+                ring:  self.0.ring,
+                sniff: self.0.sniff.is_some(),
+                start: true,
+            },
         );
         DmaStream(self.0)
     }
@@ -202,6 +375,7 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaConfig<Double<T, R, W>> {
             ch_from: from,
             pace: DmaPace::Source,
             swap: false,
+            ring: 0u8,
             first: true,
             _p: PhantomData,
         })
@@ -212,23 +386,55 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaConfig<Double<T, R, W>> {
         self.0.pace = v
     }
     #[inline]
+    pub fn pace_timer(&mut self, timer: u8, num: u16, den: u16) {
+        self.0.pace = DmaPace::timer(timer, num, den)
+    }
+    #[inline]
     pub fn bit_swap(&mut self, swap: bool) {
         self.0.swap = swap
     }
     #[inline]
+    pub fn ring_read(&mut self, size_log2: u8) -> bool {
+        if !is_ring_aligned(self.0.ch_from.rx_info().0, size_log2) {
+            return false;
+        }
+        self.0.ring = size_log2;
+        true
+    }
+    #[inline]
+    pub fn ring_write(&mut self, size_log2: u8) -> bool {
+        if !is_ring_aligned(self.0.ch_to.tx_info().0, size_log2) {
+            return false;
+        }
+        self.0.ring = if size_log2 == 0 { 0 } else { size_log2 | 0x10 };
+        true
+    }
+    #[inline]
     pub fn start(self) -> DmaStream<Double<T, R, W>> {
         dsb();
         compiler_fence(Ordering::SeqCst);
         self.0.ch1.setup(
             &self.0.ch_from,
             &self.0.ch_to,
-            self.0.swap,
-            &self.0.pace,
-            true,
+            Setup {
+                pace:  &self.0.pace,
+                swap:  self.0.swap,
+                ring:  self.0.ring,
+                sniff: false,
+                start: true,
+            },
         );
         DmaStream(self.0)
     }
 }
+impl<D: DmaDirection> DmaStream<D> {
+    #[inline]
+    fn into_inner(self) -> D {
+        // NOTE(sf): Skips 'Drop', only call this once the channels are idle.
+        let v = ManuallyDrop::new(self);
+        unsafe { read(&v.0) }
+    }
+}
 impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Single<T, R, W>> {
     #[inline]
     pub fn wait(self) {
@@ -237,6 +443,15 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Single<T, R, W>> {
         }
         dsb();
         compiler_fence(Ordering::SeqCst);
+        self.into_inner();
+    }
+    #[inline]
+    pub fn abort(self) -> (R, W) {
+        self.0.ch.abort(None);
+        dsb();
+        compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
+        (v.ch_from, v.ch_to)
     }
     #[inline]
     pub fn is_done(&self) -> bool {
@@ -250,6 +465,20 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Single<T, R, W>> {
     pub fn irq1_state(&self) -> bool {
         self.0.ch.irq1_state()
     }
+    #[inline]
+    pub fn remaining(&self) -> u32 {
+        self.0.ch.ptr().ch_trans_count().read().bits()
+    }
+    #[inline]
+    pub fn transferred(&self) -> u32 {
+        self.0.count.saturating_sub(self.remaining())
+    }
+    #[inline]
+    pub fn sniff_result(&self) -> u32 {
+        // NOTE(sf): There's only one sniffer, so this is only valid if no other
+        //           channel was started with sniffing after this one.
+        unsafe { DMA::steal() }.sniff_data().read().bits()
+    }
 }
 impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Double<T, R, W>> {
     #[inline]
@@ -259,6 +488,15 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Double<T, R, W>> {
         }
         dsb();
         compiler_fence(Ordering::SeqCst);
+        self.into_inner();
+    }
+    #[inline]
+    pub fn abort(self) -> (R, W) {
+        self.0.ch1.abort(Some(self.0.ch2));
+        dsb();
+        compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
+        (v.ch_from, v.ch_to)
     }
     #[inline]
     pub fn is_done(&self) -> bool {
@@ -276,38 +514,94 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaStream<Double<T, R, W>> {
     pub fn irq1_state(&self) -> bool {
         if self.0.first { self.0.ch1.irq1_state() } else { self.0.ch2.irq1_state() }
     }
-    pub fn read_next<S: DmaReader<T>>(self, next: S) -> DmaStream<DoubleUp<T, R, W, S>> {
+    pub fn read_next<S: DmaReader<T>>(self, next: S) -> Result<DmaStream<DoubleUp<T, R, W, S>>, (DmaStream<Double<T, R, W>>, S)> {
+        // NOTE(sf): A read ring wraps on the address of the buffer, so 'next'
+        //           has to be aligned the same way the first one was.
+        if self.0.ring & 0x10 == 0 && !is_ring_aligned(next.rx_info().0, self.0.ring & 0xF) {
+            return Err((self, next));
+        }
         dsb();
         compiler_fence(Ordering::SeqCst);
         if self.0.first {
-            self.0.ch2.setup(&next, &self.0.ch_to, self.0.swap, &self.0.pace, false);
+            self.0.ch2.setup(
+                &next,
+                &self.0.ch_to,
+                Setup {
+                    pace:  &self.0.pace,
+                    swap:  self.0.swap,
+                    ring:  self.0.ring,
+                    sniff: false,
+                    start: false,
+                },
+            );
         } else {
-            self.0.ch1.setup(&next, &self.0.ch_to, self.0.swap, &self.0.pace, false);
+            self.0.ch1.setup(
+                &next,
+                &self.0.ch_to,
+                Setup {
+                    pace:  &self.0.pace,
+                    swap:  self.0.swap,
+                    ring:  self.0.ring,
+                    sniff: false,
+                    start: false,
+                },
+            );
         }
         if self.0.first {
             self.0.ch1.chain(self.0.ch2);
         } else {
             self.0.ch2.chain(self.0.ch1);
         }
-        DmaStream(DoubleUp { ch: self.0, state: next })
+        Ok(DmaStream(DoubleUp { ch: self.into_inner(), state: next }))
     }
-    pub fn write_next<S: DmaWriter<T>>(self, next: S) -> DmaStream<DoubleUp<T, R, W, S>> {
+    pub fn write_next<S: DmaWriter<T>>(self, next: S) -> Result<DmaStream<DoubleUp<T, R, W, S>>, (DmaStream<Double<T, R, W>>, S)> {
+        if self.0.ring & 0x10 != 0 && !is_ring_aligned(next.tx_info().0, self.0.ring & 0xF) {
+            return Err((self, next));
+        }
         dsb();
         compiler_fence(Ordering::SeqCst);
         if self.0.first {
-            self.0.ch2.setup(&self.0.ch_from, &next, self.0.swap, &self.0.pace, false);
+            self.0.ch2.setup(
+                &self.0.ch_from,
+                &next,
+                Setup {
+                    pace:  &self.0.pace,
+                    swap:  self.0.swap,
+                    ring:  self.0.ring,
+                    sniff: false,
+                    start: false,
+                },
+            );
         } else {
-            self.0.ch1.setup(&self.0.ch_from, &next, self.0.swap, &self.0.pace, false);
+            self.0.ch1.setup(
+                &self.0.ch_from,
+                &next,
+                Setup {
+                    pace:  &self.0.pace,
+                    swap:  self.0.swap,
+                    ring:  self.0.ring,
+                    sniff: false,
+                    start: false,
+                },
+            );
         }
         if self.0.first {
             self.0.ch1.chain(self.0.ch2);
         } else {
             self.0.ch2.chain(self.0.ch1);
         }
-        DmaStream(DoubleUp { ch: self.0, state: next })
+        Ok(DmaStream(DoubleUp { ch: self.into_inner(), state: next }))
     }
 }
 impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, S> DmaStream<DoubleUp<T, R, W, S>> {
+    #[inline]
+    pub fn abort(self) -> (R, W, S) {
+        self.0.ch.ch1.abort(Some(self.0.ch.ch2));
+        dsb();
+        compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
+        (v.ch.ch_from, v.ch.ch_to, v.state)
+    }
     #[inline]
     pub fn is_done(&self) -> bool {
         if self.0.ch.first {
@@ -333,16 +627,18 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, S: DmaReader<T>> DmaStream<Do
         }
         dsb();
         compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
         (
-            self.0.ch.ch_from,
+            v.ch.ch_from,
             DmaStream(Double {
-                ch1:     self.0.ch.ch1,
-                ch2:     self.0.ch.ch2,
-                ch_to:   self.0.ch.ch_to,
-                ch_from: self.0.state,
-                pace:    self.0.ch.pace,
-                first:   !self.0.ch.first,
-                swap:    self.0.ch.swap,
+                ch1:     v.ch.ch1,
+                ch2:     v.ch.ch2,
+                ch_to:   v.ch.ch_to,
+                ch_from: v.state,
+                pace:    v.ch.pace,
+                ring:    v.ch.ring,
+                first:   !v.ch.first,
+                swap:    v.ch.swap,
                 _p:      PhantomData,
             }),
         )
@@ -356,16 +652,18 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, S: DmaWriter<T>> DmaStream<Do
         }
         dsb();
         compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
         (
-            self.0.ch.ch_to,
+            v.ch.ch_to,
             DmaStream(Double {
-                ch1:     self.0.ch.ch1,
-                ch2:     self.0.ch.ch2,
-                ch_to:   self.0.state,
-                ch_from: self.0.ch.ch_from,
-                pace:    self.0.ch.pace,
-                first:   !self.0.ch.first,
-                swap:    self.0.ch.swap,
+                ch1:     v.ch.ch1,
+                ch2:     v.ch.ch2,
+                ch_to:   v.state,
+                ch_from: v.ch.ch_from,
+                pace:    v.ch.pace,
+                ring:    v.ch.ring,
+                first:   !v.ch.first,
+                swap:    v.ch.swap,
                 _p:      PhantomData,
             }),
         )
@@ -406,16 +704,24 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, B: DmaReadWrite<T>> DmaConfig
         self.0.ch1.setup(
             &self.0.ch_from,
             &self.0.ch_bi,
-            self.0.swap,
-            &self.0.pace_from,
-            false,
+            Setup {
+                pace:  &self.0.pace_from,
+                swap:  self.0.swap,
+                ring:  0u8,
+                sniff: false,
+                start: false,
+            },
         );
         self.0.ch2.setup(
             &self.0.ch_bi,
             &self.0.ch_to,
-            self.0.swap,
-            &self.0.pace_to,
-            false,
+            Setup {
+                pace:  &self.0.pace_to,
+                swap:  self.0.swap,
+                ring:  0u8,
+                sniff: false,
+                start: false,
+            },
         );
         self.0.ch1.link(self.0.ch2);
         DmaStream(self.0)
@@ -429,6 +735,15 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, B: DmaReadWrite<T>> DmaStream
         }
         dsb();
         compiler_fence(Ordering::SeqCst);
+        self.into_inner();
+    }
+    #[inline]
+    pub fn abort(self) -> (R, B, W) {
+        self.0.ch1.abort(Some(self.0.ch2));
+        dsb();
+        compiler_fence(Ordering::SeqCst);
+        let v = self.into_inner();
+        (v.ch_from, v.ch_bi, v.ch_to)
     }
     #[inline]
     pub fn is_done(&self) -> bool {
@@ -444,6 +759,117 @@ impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, B: DmaReadWrite<T>> DmaStream
     }
 }
 
+impl<D: DmaDirection> Drop for DmaStream<D> {
+    #[inline]
+    fn drop(&mut self) {
+        // NOTE(sf): The endpoints can borrow memory, so the channels have to
+        //           be stopped before the borrow ends or they'd keep writing
+        //           into whatever reuses it.
+        let (a, b) = self.0.channels();
+        a.abort(b);
+        dsb();
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<'a, T: DmaWord> DmaMem<'a, T> {
+    #[inline]
+    pub fn new(b: &'a [T]) -> DmaMem<'a, T> {
+        DmaMem(b)
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        self.0
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> &'a [T] {
+        self.0
+    }
+}
+impl<'a, T: DmaWord> DmaMemMut<'a, T> {
+    #[inline]
+    pub fn new(b: &'a mut [T]) -> DmaMemMut<'a, T> {
+        DmaMemMut(b)
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        self.0
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> &'a mut [T] {
+        self.0
+    }
+}
+
+impl<'a, T: DmaWord> DmaReader<T> for DmaMem<'a, T> {
+    #[inline]
+    fn rx_req(&self) -> Option<u8> {
+        None
+    }
+    #[inline]
+    fn rx_info(&self) -> (u32, u32) {
+        (self.0.as_ptr() as u32, self.0.len() as u32)
+    }
+    #[inline]
+    fn rx_incremented(&self) -> bool {
+        true
+    }
+}
+impl<'a, T: DmaWord> DmaReader<T> for DmaMemMut<'a, T> {
+    #[inline]
+    fn rx_req(&self) -> Option<u8> {
+        None
+    }
+    #[inline]
+    fn rx_info(&self) -> (u32, u32) {
+        (self.0.as_ptr() as u32, self.0.len() as u32)
+    }
+    #[inline]
+    fn rx_incremented(&self) -> bool {
+        true
+    }
+}
+impl<'a, T: DmaWord> DmaWriter<T> for DmaMemMut<'a, T> {
+    #[inline]
+    fn tx_req(&self) -> Option<u8> {
+        None
+    }
+    #[inline]
+    fn tx_info(&self) -> (u32, u32) {
+        (self.0.as_ptr() as u32, self.0.len() as u32)
+    }
+    #[inline]
+    fn tx_incremented(&self) -> bool {
+        true
+    }
+}
+impl<'a, T: DmaWord> DmaReadWrite<T> for DmaMemMut<'a, T> {}
+
+impl<'a, T: DmaWord> From<&'a [T]> for DmaMem<'a, T> {
+    #[inline]
+    fn from(v: &'a [T]) -> DmaMem<'a, T> {
+        DmaMem(v)
+    }
+}
+impl<'a, T: DmaWord> From<&'a mut [T]> for DmaMemMut<'a, T> {
+    #[inline]
+    fn from(v: &'a mut [T]) -> DmaMemMut<'a, T> {
+        DmaMemMut(v)
+    }
+}
+
+impl Copy for SniffMode {}
+impl Clone for SniffMode {
+    #[inline]
+    fn clone(&self) -> SniffMode {
+        *self
+    }
+}
+
 impl Copy for Dma {}
 impl Clone for Dma {
     #[inline]
@@ -456,12 +882,21 @@ impl DmaWord for u8 {}
 impl DmaWord for u16 {}
 impl DmaWord for u32 {}
 
+#[inline]
+fn is_ring_aligned(addr: u32, size_log2: u8) -> bool {
+    // NOTE(sf): RING_SIZE is only 4 bits, anything larger than 32k is invalid
+    //           and the buffer must start on a (1 << size_log2) boundary.
+    size_log2 < 0x10 && addr & (unsafe { 1u32.unchecked_shl(size_log2 as u32) } - 1) == 0
+}
+
 pub mod mode {
     extern crate core;
 
     use core::marker::PhantomData;
 
-    use crate::dma::{Dma, DmaDirection, DmaPace, DmaReadWrite, DmaReader, DmaWord, DmaWriter};
+    use core::option::Option::{self, None, Some};
+
+    use crate::dma::{Dma, DmaDirection, DmaPace, DmaReadWrite, DmaReader, DmaWord, DmaWriter, SniffMode};
 
     pub struct Single<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> {
         pub(super) ch:      Dma,
@@ -469,6 +904,10 @@ pub mod mode {
         pub(super) ch_from: R,
         pub(super) pace:    DmaPace,
         pub(super) swap:    bool,
+        pub(super) ring:    u8,
+        pub(super) seed:    u32,
+        pub(super) count:   u32,
+        pub(super) sniff:   Option<SniffMode>,
         pub(super) _p:      PhantomData<T>,
     }
     pub struct Double<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> {
@@ -477,6 +916,7 @@ pub mod mode {
         pub(super) ch_to:   W,
         pub(super) ch_from: R,
         pub(super) pace:    DmaPace,
+        pub(super) ring:    u8,
         pub(super) first:   bool,
         pub(super) swap:    bool,
         pub(super) _p:      PhantomData<T>,
@@ -497,8 +937,28 @@ pub mod mode {
         pub(super) _p:        PhantomData<T>,
     }
 
-    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaDirection for Single<T, R, W> {}
-    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaDirection for Double<T, R, W> {}
-    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, S> DmaDirection for DoubleUp<T, R, W, S> {}
-    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, B: DmaReadWrite<T>> DmaDirection for BiDirection<T, R, W, B> {}
+    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaDirection for Single<T, R, W> {
+        #[inline]
+        fn channels(&self) -> (Dma, Option<Dma>) {
+            (self.ch, None)
+        }
+    }
+    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>> DmaDirection for Double<T, R, W> {
+        #[inline]
+        fn channels(&self) -> (Dma, Option<Dma>) {
+            (self.ch1, Some(self.ch2))
+        }
+    }
+    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, S> DmaDirection for DoubleUp<T, R, W, S> {
+        #[inline]
+        fn channels(&self) -> (Dma, Option<Dma>) {
+            (self.ch.ch1, Some(self.ch.ch2))
+        }
+    }
+    impl<T: DmaWord, R: DmaReader<T>, W: DmaWriter<T>, B: DmaReadWrite<T>> DmaDirection for BiDirection<T, R, W, B> {
+        #[inline]
+        fn channels(&self) -> (Dma, Option<Dma>) {
+            (self.ch1, Some(self.ch2))
+        }
+    }
 }
@@ -21,16 +21,30 @@
 
 extern crate core;
 
-use core::marker::PhantomData;
+use core::iter::Iterator;
+use core::marker::{Copy, PhantomData};
+use core::mem::{MaybeUninit, size_of};
 use core::option::Option::{self, None, Some};
+use core::slice::from_raw_parts;
 
 use crate::asm::{nop, sev, wfe};
+use crate::cores::Core;
+use crate::int::{Acknowledge, Interrupt};
 use crate::pac::SIO;
 
 pub struct Fifo {
     s:  SIO,
     _p: PhantomData<*const ()>,
 }
+pub struct Channel<T: ChannelValue> {
+    f:  Fifo,
+    _p: PhantomData<T>,
+}
+
+// NOTE(sf): Values are sent as their raw bytes, so only types without padding
+//           (and where every bit pattern is valid) can go over a 'Channel'.
+//           This is sealed to the integer types and arrays of them.
+pub trait ChannelValue: Copy + sealed::Sealed {}
 
 impl Fifo {
     #[inline]
@@ -86,3 +100,117 @@ impl Fifo {
         true
     }
 }
+impl<T: ChannelValue> Channel<T> {
+    // NOTE(sf): Zero-sized types still send a word so the receiver syncs.
+    const WORDS: usize = if size_of::<T>() == 0 { 1 } else { size_of::<T>().div_ceil(4) };
+
+    #[inline]
+    pub fn new() -> Channel<T> {
+        Channel { f: Fifo::get(), _p: PhantomData }
+    }
+
+    #[inline]
+    pub fn send(&mut self, v: T) {
+        self.send_from(0, &v)
+    }
+    #[inline]
+    pub fn recv(&mut self) -> T {
+        let mut v = MaybeUninit::<T>::uninit();
+        self.recv_into(0, &mut v);
+        unsafe { v.assume_init() }
+    }
+    #[inline]
+    pub fn irq(&self) -> Interrupt {
+        match Core::current() {
+            Core::C0 => Interrupt::Sio0,
+            Core::C1 => Interrupt::Sio1,
+        }
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !self.f.is_read_ready()
+    }
+    #[inline]
+    pub fn set_interrupt(&self, en: bool) {
+        // NOTE(sf): The SIO FIFO interrupt fires on the receiving core while
+        //           there's data in its RX FIFO, so the receiver can sleep with
+        //           'wait_for_interrupt' and wake up when a message arrives.
+        self.irq().set(en)
+    }
+    #[inline]
+    pub fn try_send(&mut self, v: T) -> bool {
+        // NOTE(sf): Only the first word is checked for space. Values larger
+        //           than a word block for the remaining words, which the other
+        //           side will be draining.
+        let b = unsafe { from_raw_parts(&v as *const T as *const u8, size_of::<T>()) };
+        if !self.f.write(word(b, 0)) {
+            return false;
+        }
+        self.send_from(1, &v);
+        true
+    }
+    #[inline]
+    pub fn try_recv(&mut self) -> Option<T> {
+        let w = self.f.read()?;
+        let mut v = MaybeUninit::<T>::uninit();
+        put(&mut v, 0, w);
+        self.recv_into(1, &mut v);
+        Some(unsafe { v.assume_init() })
+    }
+
+    fn send_from(&mut self, n: usize, v: &T) {
+        let b = unsafe { from_raw_parts(v as *const T as *const u8, size_of::<T>()) };
+        for i in n..Self::WORDS {
+            self.f.write_block(word(b, i));
+        }
+    }
+    fn recv_into(&mut self, n: usize, v: &mut MaybeUninit<T>) {
+        for i in n..Self::WORDS {
+            put(v, i, self.f.read_block());
+        }
+    }
+}
+
+impl<T: ChannelValue> Acknowledge for Channel<T> {
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        // Clear any sticky overflow/underflow flags, they also raise the IRQ.
+        self.f.s.fifo_st().write(|r| r.wof().clear_bit_by_one().roe().clear_bit_by_one());
+        self.f.is_read_ready()
+    }
+}
+
+#[inline]
+fn word(b: &[u8], i: usize) -> u32 {
+    let mut v = [0u8; 4];
+    for (x, y) in v.iter_mut().zip(b.iter().skip(i * 4)) {
+        *x = *y;
+    }
+    u32::from_le_bytes(v)
+}
+#[inline]
+fn put<T>(v: &mut MaybeUninit<T>, i: usize, w: u32) {
+    let (n, p) = (size_of::<T>(), v.as_mut_ptr() as *mut u8);
+    for (j, x) in w.to_le_bytes().iter().enumerate() {
+        if i * 4 + j >= n {
+            break;
+        }
+        unsafe { p.add(i * 4 + j).write(*x) };
+    }
+}
+
+macro_rules! channel_value {
+    ($($t:ty),+) => {$(
+        impl sealed::Sealed for $t {}
+        impl ChannelValue for $t {}
+    )+};
+}
+
+channel_value!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl<T: ChannelValue, const N: usize> sealed::Sealed for [T; N] {}
+impl<T: ChannelValue, const N: usize> ChannelValue for [T; N] {}
+
+mod sealed {
+    pub trait Sealed {}
+}
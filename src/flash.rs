@@ -0,0 +1,209 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+extern crate cortex_m;
+
+use core::fmt::{self, Debug, Formatter};
+use core::mem::transmute;
+use core::option::Option::Some;
+use core::ptr::{copy_nonoverlapping, read_volatile, write_volatile};
+use core::result::Result::{self, Err, Ok};
+
+use cortex_m::interrupt::free;
+
+use crate::BOOT2_FIRMWARE;
+
+const SSI_SR: *mut u32 = 0x18000028 as *mut u32;
+const SSI_DR0: *mut u32 = 0x18000060 as *mut u32;
+const QSPI_SS_CTRL: *mut u32 = 0x4001800C as *mut u32;
+
+pub const PAGE_SIZE: u32 = 0x100u32;
+pub const SECTOR_SIZE: u32 = 0x1000u32;
+
+pub enum FlashError {
+    Misaligned,
+    OutOfBounds,
+    InvalidBuffer,
+}
+
+pub(crate) struct Rom {
+    connect:  extern "C" fn(),
+    exit_xip: extern "C" fn(),
+    flush:    extern "C" fn(),
+    erase:    extern "C" fn(u32, usize, u32, u8),
+    program:  extern "C" fn(u32, *const u8, usize),
+}
+
+impl Rom {
+    #[inline]
+    pub(crate) fn get() -> Rom {
+        unsafe {
+            Rom {
+                connect:  transmute(rom_func(b'I', b'F')),
+                exit_xip: transmute(rom_func(b'E', b'X')),
+                flush:    transmute(rom_func(b'F', b'C')),
+                erase:    transmute(rom_func(b'R', b'E')),
+                program:  transmute(rom_func(b'R', b'P')),
+            }
+        }
+    }
+}
+
+impl Debug for FlashError {
+    #[cfg(feature = "debug")]
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FlashError::Misaligned => f.write_str("Misaligned"),
+            FlashError::OutOfBounds => f.write_str("OutOfBounds"),
+            FlashError::InvalidBuffer => f.write_str("InvalidBuffer"),
+        }
+    }
+    #[cfg(not(feature = "debug"))]
+    #[inline]
+    fn fmt(&self, _f: &mut Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+// NOTE(sf): Offsets are from the start of flash, not the XIP address. Interrupts
+//           are disabled while XIP is off, but core1 must not be running from
+//           flash while these are called.
+pub fn erase(offset: u32, len: u32) -> Result<(), FlashError> {
+    if offset % SECTOR_SIZE != 0 || len % SECTOR_SIZE != 0 {
+        return Err(FlashError::Misaligned);
+    }
+    check_bounds(offset, len)?;
+    if len == 0 {
+        return Ok(());
+    }
+    let r = Rom::get();
+    free(|_| unsafe { flash_erase(&r, offset, len) });
+    Ok(())
+}
+pub fn program(offset: u32, data: &[u8]) -> Result<(), FlashError> {
+    if offset % PAGE_SIZE != 0 || data.len() as u32 % PAGE_SIZE != 0 {
+        return Err(FlashError::Misaligned);
+    }
+    check_bounds(offset, data.len() as u32)?;
+    // NOTE(sf): XIP is off while programming, so the data can't be read from
+    //           flash. It has to be copied into RAM first.
+    if (data.as_ptr() as u32) < 0x20000000 {
+        return Err(FlashError::InvalidBuffer);
+    }
+    if data.is_empty() {
+        return Ok(());
+    }
+    let r = Rom::get();
+    free(|_| unsafe { flash_program(&r, offset, data) });
+    Ok(())
+}
+
+#[inline]
+pub(crate) fn rom_func(a: u8, b: u8) -> usize {
+    unsafe {
+        let l: extern "C" fn(*const u16, u32) -> usize = transmute(*(0x18 as *const u16) as usize);
+        l(*(0x14 as *const u16) as usize as *const u16, a as u32 | (b as u32).unchecked_shl(8))
+    }
+}
+
+// NOTE(sf): Everything below runs while XIP is disabled, so it has to live in
+//           RAM and can't call into anything that lives in flash. The second
+//           stage bootloader is copied onto the stack before XIP is disabled,
+//           so it can be used to put the flash back into XIP mode.
+
+/// # Safety
+///
+/// XIP is off for the whole command. Interrupts must be disabled and core1
+/// must not be executing from flash while this is called.
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+pub(crate) unsafe fn flash_cmd(rom: &Rom, buf: &mut [u8]) {
+    let mut b = [0u32; 64];
+    xip_exit(rom, &mut b);
+    ss_set(0x2);
+    let (n, mut t, mut r) = (buf.len(), 0usize, 0usize);
+    while t < n || r < n {
+        let s = unsafe { read_volatile(SSI_SR) };
+        // NOTE(sf): Don't get more than 14 bytes ahead of the RX FIFO (16 deep)
+        //           or it'll overflow and we'll lose data.
+        if s & 0x2 != 0 && t < n && r + 14 > t {
+            unsafe { write_volatile(SSI_DR0, *buf.as_ptr().add(t) as u32) };
+            t += 1;
+        }
+        if s & 0x8 != 0 && r < n {
+            unsafe { *buf.as_mut_ptr().add(r) = read_volatile(SSI_DR0) as u8 };
+            r += 1;
+        }
+    }
+    ss_set(0x3);
+    xip_enter(rom, &b);
+}
+
+/// # Safety
+///
+/// Same as 'flash_cmd'.
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn flash_erase(rom: &Rom, offset: u32, len: u32) {
+    let mut b = [0u32; 64];
+    xip_exit(rom, &mut b);
+    // 0xD8 is the 64k block erase command, the ROM uses it when it can.
+    (rom.erase)(offset, len as usize, 0x10000, 0xD8);
+    xip_enter(rom, &b);
+}
+/// # Safety
+///
+/// Same as 'flash_cmd'.
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn flash_program(rom: &Rom, offset: u32, data: &[u8]) {
+    let mut b = [0u32; 64];
+    xip_exit(rom, &mut b);
+    (rom.program)(offset, data.as_ptr(), data.len());
+    xip_enter(rom, &b);
+}
+
+#[inline]
+fn check_bounds(offset: u32, len: u32) -> Result<(), FlashError> {
+    // NOTE(sf): 16MB is the max the XIP window can address.
+    match offset.checked_add(len) {
+        Some(v) if v <= 0x1000000 => Ok(()),
+        _ => Err(FlashError::OutOfBounds),
+    }
+}
+#[inline(always)]
+fn ss_set(v: u32) {
+    unsafe { write_volatile(QSPI_SS_CTRL, (read_volatile(QSPI_SS_CTRL) & !0x300) | v.unchecked_shl(8)) }
+}
+#[inline(always)]
+fn xip_exit(rom: &Rom, b: &mut [u32; 64]) {
+    unsafe { copy_nonoverlapping(BOOT2_FIRMWARE.as_ptr(), b.as_mut_ptr() as *mut u8, 256) };
+    (rom.connect)();
+    (rom.exit_xip)();
+}
+#[inline(always)]
+fn xip_enter(rom: &Rom, b: &[u32; 64]) {
+    (rom.flush)();
+    let f: extern "C" fn() = unsafe { transmute(b.as_ptr() as usize + 1) };
+    f();
+}
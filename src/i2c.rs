@@ -21,12 +21,14 @@
 
 extern crate core;
 
+use core::assert;
 use core::clone::Clone;
-use core::convert::From;
+use core::cmp::Ord;
+use core::convert::{From, TryFrom};
 use core::fmt::{self, Debug, Formatter};
 use core::iter::Iterator;
 use core::marker::{PhantomData, Send};
-use core::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut, Drop};
 use core::option::Option::{self, None, Some};
 use core::ptr::NonNull;
 use core::result::Result::{self, Err, Ok};
@@ -34,9 +36,10 @@ use core::result::Result::{self, Err, Ok};
 use crate::Board;
 use crate::asm::nop;
 use crate::i2c::mode::{Controller, Peripheral, State};
+use crate::int::Interrupt;
 use crate::pac::i2c0::RegisterBlock;
 use crate::pac::{I2C0, I2C1, RESETS};
-use crate::pin::{I2cID, PinFunction, PinID, pins_i2c};
+use crate::pin::{I2cID, PinFunction, PinID, pins_i2c, valid_i2c};
 
 pub enum I2cError {
     WouldBlock,
@@ -60,15 +63,25 @@ pub enum I2cEvent {
     Write,
 }
 
+pub enum I2cOp<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
 pub enum I2cBus<'a, M: I2cMode> {
     Owned(I2c<M>),
     Shared(&'a mut I2c<M>),
     Duplicated((I2c<M>, PhantomData<&'a I2c<M>>)),
 }
 
+pub struct I2cDev {
+    pub sda: PinID,
+    pub scl: PinID,
+}
 pub struct I2cAddress(u16);
 pub struct I2c<M: I2cMode> {
     dev:  NonNull<RegisterBlock>,
+    own:  bool,
     mode: M,
 }
 
@@ -79,6 +92,31 @@ pub trait I2cMode: Clone {
 pub type I2cController = I2c<Controller>;
 pub type I2cPeripheral = I2c<Peripheral>;
 
+impl I2cOp<'_> {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            I2cOp::Read(b) => b.is_empty(),
+            I2cOp::Write(b) => b.is_empty(),
+        }
+    }
+}
+impl I2cDev {
+    #[inline]
+    pub const fn checked(sda: PinID, scl: PinID) -> I2cDev {
+        // NOTE(sf): This is only a compile error when evaluated in a const
+        //           context, ie: 'const D: I2cDev = I2cDev::checked(..);'.
+        //           At runtime it just panics, use 'new' to get an error.
+        assert!(valid_i2c(sda, scl), "invalid I2C pins");
+        I2cDev { sda, scl }
+    }
+
+    #[inline]
+    pub fn new(sda: PinID, scl: PinID) -> Result<I2cDev, I2cError> {
+        pins_i2c(&sda, &scl).ok_or(I2cError::InvalidPins)?;
+        Ok(I2cDev { sda, scl })
+    }
+}
 impl I2cAddress {
     #[inline]
     pub const fn new_7bit(v: u8) -> I2cAddress {
@@ -138,12 +176,12 @@ impl I2c<Peripheral> {
                 self.mode.state = State::Reading;
                 Some(I2cEvent::Read)
             },
-            State::Active if !self.rx_is_empty() => {
+            State::Active if s.rx_full().bit_is_set() => {
                 self.mode.state = State::Writing;
                 Some(I2cEvent::Write)
             },
             State::Reading if s.rd_req().bit_is_set() => Some(I2cEvent::Read),
-            State::Writing if !self.rx_is_empty() => Some(I2cEvent::Write),
+            State::Writing if s.rx_full().bit_is_set() => Some(I2cEvent::Write),
             State::Reading | State::Writing if s.restart_det().bit_is_set() => {
                 let _ = d.ic_clr_restart_det().read();
                 let _ = d.ic_clr_start_det().read();
@@ -184,6 +222,50 @@ impl I2c<Peripheral> {
         let _ = d.ic_clr_rd_req().read();
         true
     }
+    // NOTE(sf): RX_FULL (and the 'Write' event) only triggers once the RX FIFO
+    //           has more than 'level' bytes, so a higher level batches bytes
+    //           at the cost of latency. Any bytes under the watermark when the
+    //           'Stop' event comes in need to be drained with 'read'.
+    #[inline]
+    pub fn set_rx_watermark(&mut self, level: u8) {
+        self.ptr()
+            .ic_rx_tl()
+            .write(|r| unsafe { r.rx_tl().bits(level.min(15)) })
+    }
+    #[inline]
+    pub fn irq(&self) -> Interrupt {
+        if self.dev.as_ptr().addr() == I2C0::PTR.addr() { Interrupt::I2c0 } else { Interrupt::I2c1 }
+    }
+    // NOTE(sf): The PAC names these backwards, a set bit unmasks the source.
+    //           'event' still works off the raw status, so these only control
+    //           what triggers the NVIC interrupt from 'irq'.
+    #[inline]
+    pub fn set_interrupts(&mut self, start: bool, read_req: bool, rx_full: bool, stop: bool) {
+        self.ptr().ic_intr_mask().modify(|_, r| {
+            r.m_start_det()
+                .bit(start)
+                .m_rd_req()
+                .bit(read_req)
+                .m_rx_full()
+                .bit(rx_full)
+                .m_stop_det()
+                .bit(stop)
+        })
+    }
+    // NOTE(sf): This clears every software cleared interrupt, which includes
+    //           the ones 'event' looks for. Only use this when not calling
+    //           'event'. RX_FULL is cleared by reading under the watermark.
+    #[inline]
+    pub fn clear_interrupts(&mut self) {
+        let _ = self.ptr().ic_clr_intr().read();
+    }
+    // NOTE(sf): TX_EMPTY triggers once the TX FIFO has 'level' bytes or less.
+    #[inline]
+    pub fn set_tx_watermark(&mut self, level: u8) {
+        self.ptr()
+            .ic_tx_tl()
+            .write(|r| unsafe { r.tx_tl().bits(level.min(15)) })
+    }
 }
 impl I2c<Controller> {
     pub const DEFAULT_FREQ: u32 = 400_000u32;
@@ -232,6 +314,26 @@ impl I2c<Controller> {
         self.write_raw_single(false, v)?;
         self.read_raw(false, true, out)
     }
+    pub fn exec(&mut self, addr: I2cAddress, ops: &mut [I2cOp<'_>]) -> Result<(), I2cError> {
+        // NOTE(sf): Empty ops are skipped, as an empty read/write resets the
+        //           bus, which would break the chain of repeated starts. The
+        //           STOP goes on the last op with data.
+        let l = match ops.iter().rposition(|v| !v.is_empty()) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        self.prepare(addr)?;
+        let mut f = true;
+        for (i, v) in ops.iter_mut().enumerate() {
+            match v {
+                _ if v.is_empty() => continue,
+                I2cOp::Read(b) => self.read_raw(f, i == l, b)?,
+                I2cOp::Write(b) => self.write_raw(i == l, b)?,
+            };
+            f = false;
+        }
+        Ok(())
+    }
 
     fn reset(&self) {
         let d = self.ptr();
@@ -440,6 +542,7 @@ impl<M: I2cMode> I2c<M> {
         sda.set_output();
         Ok(I2c {
             dev:  unsafe { NonNull::new_unchecked(d as *mut RegisterBlock) },
+            own:  true,
             mode: Controller,
         })
     }
@@ -488,16 +591,8 @@ impl<M: I2cMode> I2c<M> {
             x.ic_tx_tl().write(|r| r.tx_tl().bits(0));
             x.ic_rx_tl().write(|r| r.rx_tl().bits(0));
             let _ = x.ic_clr_intr().read();
-            x.ic_intr_mask().write_with_zero(|r| {
-                r.m_start_det()
-                    .disabled()
-                    .m_rd_req()
-                    .disabled()
-                    .m_rx_full()
-                    .disabled()
-                    .m_stop_det()
-                    .disabled()
-            });
+            // Mask everything, see 'set_interrupts'.
+            x.ic_intr_mask().write_with_zero(|r| r);
             x.ic_enable().write(|r| r.enable().enabled());
         }
         sda.set_function(PinFunction::I2c);
@@ -506,6 +601,7 @@ impl<M: I2cMode> I2c<M> {
         sda.set_input();
         Ok(I2c {
             dev:  unsafe { NonNull::new_unchecked(d as *mut RegisterBlock) },
+            own:  true,
             mode: Peripheral { state: State::Idle },
         })
     }
@@ -581,6 +677,17 @@ impl Iterator for I2c<Peripheral> {
     }
 }
 
+impl<M: I2cMode> Drop for I2c<M> {
+    #[inline]
+    fn drop(&mut self) {
+        // NOTE(sf): Only the I2c returned by 'new' owns the device, the copy
+        //           made for 'I2cBus::Duplicated' does not.
+        if self.own {
+            self.close();
+        }
+    }
+}
+
 impl<M: I2cMode> Deref for I2cBus<'_, M> {
     type Target = I2c<M>;
 
@@ -615,6 +722,7 @@ impl<'a, M: I2cMode> From<&'a I2c<M>> for I2cBus<'a, M> {
         I2cBus::Duplicated((
             I2c {
                 dev:  v.dev,
+                own:  false,
                 mode: v.mode.clone(),
             },
             PhantomData,
@@ -628,6 +736,14 @@ impl<'a, M: I2cMode> From<&'a mut I2c<M>> for I2cBus<'a, M> {
     }
 }
 
+impl TryFrom<(PinID, PinID)> for I2cDev {
+    type Error = I2cError;
+
+    #[inline]
+    fn try_from(v: (PinID, PinID)) -> Result<I2cDev, I2cError> {
+        I2cDev::new(v.0, v.1)
+    }
+}
 impl From<u8> for I2cAddress {
     #[inline]
     fn from(v: u8) -> I2cAddress {
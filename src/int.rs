@@ -26,11 +26,13 @@ use core::cell::UnsafeCell;
 use core::clone::Clone;
 use core::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use core::convert::{From, Into};
+use core::iter::Iterator;
 use core::marker::{Copy, PhantomData};
 use core::mem::{MaybeUninit, size_of};
-use core::ops::{Index, IndexMut};
-use core::option::Option;
+use core::ops::{Deref, FnOnce, Index, IndexMut};
+use core::option::Option::{self, None, Some};
 use core::ptr::copy_nonoverlapping;
+use core::sync::atomic::{self, compiler_fence};
 
 use cortex_m::interrupt::free;
 
@@ -76,6 +78,7 @@ pub enum Interrupt {
     Sw5     = 31,
 }
 
+pub struct SoftwareInterrupt(Interrupt);
 pub struct Ack<'a>([Entry<'a>; 32]);
 pub struct Custom<'a>([Call<'a>; 32]);
 pub struct Standard(PhantomData<*const ()>);
@@ -181,6 +184,45 @@ impl Interrupt {
     fn shift(&self) -> u32 {
         ((*self as u32) % 4) * 8
     }
+    #[inline]
+    fn vector(&self) -> usize {
+        // IRQs start after the 16 system exceptions.
+        (0x10 + (*self as usize)).min(47)
+    }
+}
+impl SoftwareInterrupt {
+    pub const SW0: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw0);
+    pub const SW1: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw1);
+    pub const SW2: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw2);
+    pub const SW3: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw3);
+    pub const SW4: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw4);
+    pub const SW5: SoftwareInterrupt = SoftwareInterrupt(Interrupt::Sw5);
+
+    #[inline]
+    pub const fn new(i: Interrupt) -> Option<SoftwareInterrupt> {
+        match i {
+            Interrupt::Sw0 | Interrupt::Sw1 | Interrupt::Sw2 | Interrupt::Sw3 | Interrupt::Sw4 | Interrupt::Sw5 => Some(SoftwareInterrupt(i)),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn interrupt(&self) -> Interrupt {
+        self.0
+    }
+    #[inline]
+    pub fn trigger_software(&self) {
+        // NOTE(sf): Each core has its own NVIC, so this only pends the interrupt
+        //           on the core that calls it. It's meant for deferring work from
+        //           a higher priority handler (or a task) to a lower one.
+        //
+        //           To signal the other core, write to the inter-core FIFO
+        //           instead, which raises 'Sio0' on core0 or 'Sio1' on core1.
+        //           The receiving core registers a handler for its Sio
+        //           interrupt and can pend a software interrupt from there if
+        //           the work should run at a lower priority.
+        set_pending(self.0, true);
+    }
 }
 impl<'a> Ack<'a> {
     #[inline]
@@ -279,10 +321,10 @@ impl<'a> InterruptHandler<Ack<'a>> {
         }))
     }
     #[inline]
-    pub fn enable(&mut self, i: Interrupt, ack: &'a mut impl Acknowledge, func: fn()) {
+    pub fn enable(&mut self, i: Interrupt, ack: &'a mut impl Acknowledge, func: fn()) -> bool {
         free(|_| {
             self.ptr().ext.0[i as usize].set(ack, func);
-            self.set_inner(i, interrupt_handler);
+            self.set_inner(i, interrupt_handler)
         })
     }
     #[inline]
@@ -321,10 +363,10 @@ impl<'a> InterruptHandler<Custom<'a>> {
         }))
     }
     #[inline]
-    pub fn enable(&mut self, i: Interrupt, v: &'a mut impl Interrupted) {
+    pub fn enable(&mut self, i: Interrupt, v: &'a mut impl Interrupted) -> bool {
         free(|_| {
             self.ptr().ext.0[i as usize].0.write(v);
-            self.set_inner(i, interrupt_handler);
+            self.set_inner(i, interrupt_handler)
         })
     }
     #[inline]
@@ -357,7 +399,7 @@ impl<'a> InterruptHandler<Object<'a>> {
     }
 
     #[inline]
-    pub fn enable(&mut self, i: Interrupt) {
+    pub fn enable(&mut self, i: Interrupt) -> bool {
         free(|_| self.set_inner(i, interrupt_handler))
     }
     #[inline]
@@ -410,7 +452,7 @@ impl<E: InterruptExtension> InterruptHandler<E> {
         free(|_| {
             // Disable the interrupt.
             i.disable();
-            let i = (0x10 + (i as usize)).min(31);
+            let i = i.vector();
             // Read directly from ROM.
             let t = Self::interrupt_table(true);
             // Reset the default value.
@@ -418,9 +460,21 @@ impl<E: InterruptExtension> InterruptHandler<E> {
         })
     }
     #[inline]
-    pub fn set(&mut self, i: Interrupt, func: extern "C" fn()) {
+    pub fn set(&mut self, i: Interrupt, func: extern "C" fn()) -> bool {
         free(|_| self.set_inner(i, func))
     }
+    #[inline]
+    pub fn is_set(&self, i: Interrupt) -> bool {
+        // NOTE(sf): Anything that isn't the flash vector table entry was put
+        //           there by us (or someone else using the table).
+        let x = i.vector();
+        unsafe { self.ptr().ints.get_unchecked(x).ptr != *(ADDR_BASE as *const usize).add(x) }
+    }
+    #[inline]
+    pub fn set_systick(&mut self, func: extern "C" fn()) {
+        // SysTick is exception 15.
+        free(|_| unsafe { self.ptr().ints.get_unchecked_mut(0xF).external = func })
+    }
 
     #[inline]
     fn interrupt_table(default: bool) -> [Func; 48] {
@@ -440,9 +494,34 @@ impl<E: InterruptExtension> InterruptHandler<E> {
         unsafe { &mut *self.0.get() }
     }
     #[inline]
-    fn set_inner(&self, i: Interrupt, func: extern "C" fn()) {
+    fn set_inner(&self, i: Interrupt, func: extern "C" fn()) -> bool {
+        // Returns true if the vector was already occupied.
+        let r = self.is_set(i);
         i.enable();
-        unsafe { self.ptr().ints.get_unchecked_mut((0x10 + (i as usize)).min(31)).external = func };
+        unsafe { self.ptr().ints.get_unchecked_mut(i.vector()).external = func };
+        r
+    }
+}
+
+impl Deref for SoftwareInterrupt {
+    type Target = Interrupt;
+
+    #[inline]
+    fn deref(&self) -> &Interrupt {
+        &self.0
+    }
+}
+impl From<SoftwareInterrupt> for Interrupt {
+    #[inline]
+    fn from(v: SoftwareInterrupt) -> Interrupt {
+        v.0
+    }
+}
+impl Copy for SoftwareInterrupt {}
+impl Clone for SoftwareInterrupt {
+    #[inline]
+    fn clone(&self) -> SoftwareInterrupt {
+        *self
     }
 }
 
@@ -561,6 +640,20 @@ pub fn wait_for_event() {
 pub fn wait_for_interrupt() {
     wfi();
 }
+// NOTE(sf): The M0+ has no BASEPRI, so instead this just masks the specified
+//           interrupts in the NVIC while 'f' runs, then restores only the ones
+//           that were enabled before. Any other interrupts can still fire.
+pub fn with_masked<R>(interrupts: &[Interrupt], f: impl FnOnce() -> R) -> R {
+    let m = interrupts.iter().fold(0u32, |m, i| m | i.value());
+    let n = unsafe { &*NVIC::PTR };
+    let e = n.iser[0].read() & m;
+    unsafe { n.icer[0].write(m) };
+    compiler_fence(atomic::Ordering::SeqCst);
+    let r = f();
+    compiler_fence(atomic::Ordering::SeqCst);
+    unsafe { n.iser[0].write(e) };
+    r
+}
 #[inline]
 pub fn is_enabled(i: Interrupt) -> bool {
     let v = i.value();
@@ -21,6 +21,7 @@
 
 extern crate core;
 
+use core::cmp::Ord;
 use core::convert::{From, Into};
 use core::default::Default;
 use core::marker::PhantomData;
@@ -167,6 +168,32 @@ impl Interpoler<Num0> {
     pub fn base_set_both(&mut self, v: u32) {
         unsafe { SIO::steal().interp0_base_1and0().write(|r| r.bits(v)) }
     }
+    #[inline]
+    pub fn blend(&mut self, a: u32, b: u32, t: u8) -> u32 {
+        self.lane0.base_set(a);
+        self.lane1.base_set(b);
+        self.lane1.accumulator_set(t as u32);
+        self.lane1.peek()
+    }
+    #[inline]
+    pub fn setup_blend(&mut self) {
+        // NOTE(sf): Blend mode is only available on Interpoler0. Lane1 gives
+        //           'base0 + (base1 - base0) * accum1[7:0] / 256' and its
+        //           signed flag can be set to treat the bases as signed.
+        self.lane0.ctrl_set(LaneConfig::new().blend(true));
+        self.lane1.ctrl_set(LaneConfig::new());
+    }
+    #[inline]
+    pub fn lut_fetch(&mut self, base: u32, index: u8, entry_size_log2: u8) -> u32 {
+        // NOTE(sf): The Interpoler can only shift right, so the index is placed
+        //           into the accumulator pre-shifted and the mask keeps only the
+        //           8 index bits, giving 'base0 + (index << entry_size_log2)'.
+        let s = entry_size_log2.min(24);
+        self.lane0.ctrl_set(LaneConfig::new().lsb_mask(s).msb_mask(s + 7));
+        self.lane0.base_set(base);
+        self.lane0.accumulator_set(unsafe { (index as u32).unchecked_shl(s as u32) });
+        self.lane0.peek()
+    }
 }
 impl Interpoler<Num1> {
     #[inline]
@@ -198,6 +225,20 @@ impl Interpoler<Num1> {
     pub fn base_set_both(&mut self, v: u32) {
         unsafe { SIO::steal().interp1_base_1and0().write(|r| r.bits(v)) }
     }
+    #[inline]
+    pub fn clamp_add(&mut self, v: i32) -> u32 {
+        self.lane0.add(v as u32);
+        self.lane0.peek()
+    }
+    #[inline]
+    pub fn setup_clamp(&mut self, min: u32, max: u32) {
+        // NOTE(sf): Clamp mode is only available on Interpoler1 and applies to
+        //           the Lane0 result, which is clamped between base0 and base1.
+        self.lane0.ctrl_set(LaneConfig::new().clamp(true));
+        self.lane0.base_set(min);
+        self.lane1.base_set(max);
+        self.lane0.accumulator_set(0);
+    }
 }
 impl Lane<Num0, Lane0> {
     #[inline]
@@ -234,7 +275,7 @@ impl Lane<Num0, Lane0> {
     }
     #[inline]
     pub fn accumulator_set(&mut self, v: u32) {
-        unsafe { SIO::steal().interp0_accum0_add().write(|r| r.bits(v)) }
+        unsafe { SIO::steal().interp0_accum0().write(|r| r.bits(v)) }
     }
     #[inline]
     pub fn ctrl_set(&mut self, v: impl Into<u32>) {
@@ -276,7 +317,7 @@ impl Lane<Num0, Lane1> {
     }
     #[inline]
     pub fn accumulator_set(&mut self, v: u32) {
-        unsafe { SIO::steal().interp0_accum1_add().write(|r| r.bits(v)) }
+        unsafe { SIO::steal().interp0_accum1().write(|r| r.bits(v)) }
     }
     #[inline]
     pub fn ctrl_set(&mut self, v: impl Into<u32>) {
@@ -318,7 +359,7 @@ impl Lane<Num1, Lane0> {
     }
     #[inline]
     pub fn accumulator_set(&mut self, v: u32) {
-        unsafe { SIO::steal().interp1_accum0_add().write(|r| r.bits(v)) }
+        unsafe { SIO::steal().interp1_accum0().write(|r| r.bits(v)) }
     }
     #[inline]
     pub fn ctrl_set(&mut self, v: impl Into<u32>) {
@@ -360,7 +401,7 @@ impl Lane<Num1, Lane1> {
     }
     #[inline]
     pub fn accumulator_set(&mut self, v: u32) {
-        unsafe { SIO::steal().interp1_accum1_add().write(|r| r.bits(v)) }
+        unsafe { SIO::steal().interp1_accum1().write(|r| r.bits(v)) }
     }
     #[inline]
     pub fn ctrl_set(&mut self, v: impl Into<u32>) {
@@ -45,6 +45,7 @@ pub mod clock;
 pub mod cores;
 pub mod dma;
 pub mod fifo;
+pub mod flash;
 pub mod i2c;
 pub mod int;
 pub mod interp;
@@ -66,6 +67,12 @@ pub use pico::*;
 #[cfg(feature = "debug")]
 pub use self::debug::uart_debug;
 
+pub mod critical {
+    // NOTE(sf): Scoped masking lives with the rest of the NVIC code in 'int',
+    //           this just gives it the 'critical::with_masked' path.
+    pub use crate::int::with_masked;
+}
+
 mod pac {
     // NOTE(sf): It looks cleaner this way instead of 'pub extern'
     extern crate rp2040_pac;
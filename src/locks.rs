@@ -20,17 +20,38 @@
 #![no_implicit_prelude]
 
 extern crate core;
+extern crate cortex_m;
 
+use core::assert;
+use core::cell::UnsafeCell;
 use core::iter::Iterator;
-use core::marker::PhantomData;
-use core::ops::Drop;
+use core::marker::{PhantomData, Send, Sync};
+use core::mem::forget;
+use core::ops::{Deref, DerefMut, Drop};
 use core::option::Option::{self, None, Some};
 
+use cortex_m::interrupt::{disable, enable};
+use cortex_m::register::primask::read;
+
 use crate::asm::nop;
 use crate::pac::SIO;
 
 pub struct Spinlock<const N: u8>(PhantomData<*const ()>);
 
+// NOTE(sf): Spinlock31 is reserved for 'atomic::with', so a Mutex can use any
+//           Spinlock from 0 to 30. Each Mutex should have it's own Spinlock
+//           number, as Mutexes sharing one will block each other.
+pub struct Mutex<T, const N: u8> {
+    v: UnsafeCell<T>,
+}
+// NOTE(sf): The guard restores the interrupt state of the core that locked
+//           it, so it can't be sent to the other core.
+pub struct MutexGuard<'a, T, const N: u8> {
+    m:  &'a Mutex<T, N>,
+    e:  bool,
+    _p: PhantomData<*const ()>,
+}
+
 pub type Spinlock0 = Spinlock<0>;
 pub type Spinlock1 = Spinlock<1>;
 pub type Spinlock2 = Spinlock<2>;
@@ -91,6 +112,56 @@ impl<const N: u8> Spinlock<N> {
     }
 }
 
+impl<T, const N: u8> Mutex<T, N> {
+    #[inline]
+    pub const fn new(v: T) -> Mutex<T, N> {
+        const { assert!(N < 31) };
+        Mutex { v: UnsafeCell::new(v) }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.v.into_inner()
+    }
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.v.get() }
+    }
+    pub fn lock(&self) -> MutexGuard<'_, T, N> {
+        let e = read().is_active();
+        loop {
+            disable();
+            if let Some(v) = Spinlock::<N>::try_claim() {
+                forget(v);
+                break;
+            }
+            // Let any interrupts run while we wait.
+            if e {
+                unsafe { enable() };
+            }
+            nop();
+        }
+        MutexGuard { m: self, e, _p: PhantomData }
+    }
+    #[inline]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, N>> {
+        let e = read().is_active();
+        disable();
+        match Spinlock::<N>::try_claim() {
+            Some(v) => {
+                forget(v);
+                Some(MutexGuard { m: self, e, _p: PhantomData })
+            },
+            None => {
+                if e {
+                    unsafe { enable() };
+                }
+                None
+            },
+        }
+    }
+}
+
 impl<const N: u8> Drop for Spinlock<N> {
     #[inline]
     fn drop(&mut self) {
@@ -98,6 +169,33 @@ impl<const N: u8> Drop for Spinlock<N> {
     }
 }
 
+impl<T, const N: u8> Drop for MutexGuard<'_, T, N> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { Spinlock::<N>::free() };
+        if self.e {
+            unsafe { enable() };
+        }
+    }
+}
+impl<T, const N: u8> Deref for MutexGuard<'_, T, N> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.m.v.get() }
+    }
+}
+impl<T, const N: u8> DerefMut for MutexGuard<'_, T, N> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.m.v.get() }
+    }
+}
+
+unsafe impl<T: Send, const N: u8> Send for Mutex<T, N> {}
+unsafe impl<T: Send, const N: u8> Sync for Mutex<T, N> {}
+
 #[inline]
 pub fn spinlock_state() -> [bool; 32] {
     let mut r = [false; 32];
@@ -107,3 +205,19 @@ pub fn spinlock_state() -> [bool; 32] {
     }
     r
 }
+
+// NOTE(sf): The 'entry' macro already does this on boot, this is for when a
+//           core was reset while holding a lock. Spinlock31 backs 'atomic::with'
+//           so don't call this while the other core might be inside it.
+/// # Safety
+///
+/// Force-frees every Spinlock, including ones held by other code on either
+/// core. Only call this when nothing can be holding a lock, such as right
+/// after a core reset.
+#[inline]
+pub unsafe fn reset_all() {
+    let d = unsafe { SIO::steal() };
+    for i in 0..32 {
+        unsafe { d.spinlock(i).write_with_zero(|r| r.bits(1)) }
+    }
+}
@@ -22,7 +22,8 @@
 extern crate core;
 
 use core::mem::zeroed;
-use core::ptr::{NonNull, write_volatile};
+use core::option::Option::{self, None, Some};
+use core::ptr::NonNull;
 use core::result::Result;
 
 use crate::atomic::{Mutex, with};
@@ -31,6 +32,7 @@ use crate::pin::gpio::Output;
 use crate::pin::pwm::PwmPin;
 use crate::pin::{Pin, PinID, setup_pins};
 use crate::static_instance;
+use crate::sys::{atomic_clear, atomic_set};
 use crate::watchdog::Watchdog;
 
 static_instance!(INSTANCE, Inner, Inner::new());
@@ -43,6 +45,7 @@ pub type MayFail<T> = Result<!, T>;
 struct Inner {
     clk:   Clock,
     dog:   Watchdog,
+    taken: bool,
     timer: Timer,
 }
 
@@ -58,6 +61,48 @@ impl Board {
         }))
     }
 
+    #[inline]
+    pub fn take() -> Option<Board> {
+        // NOTE(sf): Only the first call returns the Board, any calls after that
+        //           (even from the other core) return None. Library code still
+        //           uses 'get' for access.
+        with(|x| {
+            let p = INSTANCE.borrow_mut(x);
+            if p.taken {
+                return None;
+            }
+            p.taken = true;
+            if !p.is_ready() {
+                p.setup();
+            }
+            Some(Board(unsafe { NonNull::new_unchecked(p) }))
+        })
+    }
+    #[inline]
+    pub fn get_pll(freq: u32) -> Option<Board> {
+        with(|x| {
+            let p = INSTANCE.borrow_mut(x);
+            if !p.is_ready() && !p.setup_pll(freq) {
+                return None;
+            }
+            Some(Board(unsafe { NonNull::new_unchecked(p) }))
+        })
+    }
+    #[inline]
+    pub fn get_xosc_peri(xosc_ref: bool) -> Board {
+        // NOTE(sf): Keeps the XOSC running to drive clk_peri (and clk_ref if
+        //           'xosc_ref' is true), so UART/SPI baud rates are crystal
+        //           accurate while clk_sys stays on the ROSC. Has no effect if
+        //           the Board was already setup.
+        with(|x| {
+            let p = INSTANCE.borrow_mut(x);
+            if !p.is_ready() {
+                p.setup_xosc_peri(xosc_ref);
+            }
+            Board(unsafe { NonNull::new_unchecked(p) })
+        })
+    }
+
     #[inline]
     pub fn sleep(&self, ms: u32) {
         self.ptr().timer.sleep_ms(ms)
@@ -83,6 +128,10 @@ impl Board {
         self.ptr().timer.current_tick()
     }
     #[inline]
+    pub fn peripheral_freq(&self) -> u32 {
+        self.ptr().clk.peri_freq()
+    }
+    #[inline]
     pub fn watchdog(&self) -> &Watchdog {
         &self.ptr().dog
     }
@@ -118,7 +167,25 @@ impl Inner {
         setup_pins(); // Setup pins first.
         self.clk = Clock::new();
         self.timer = Timer::new(&self.clk);
-        self.dog = Watchdog::new(self.clk.freq());
+        self.dog = Watchdog::new(self.clk.ref_freq());
+    }
+    #[inline]
+    fn setup_pll(&mut self, freq: u32) -> bool {
+        setup_pins(); // Setup pins first.
+        self.clk = match Clock::new_pll(freq) {
+            Some(c) => c,
+            None => return false,
+        };
+        self.timer = Timer::new(&self.clk);
+        self.dog = Watchdog::new(self.clk.ref_freq());
+        true
+    }
+    #[inline]
+    fn setup_xosc_peri(&mut self, xosc_ref: bool) {
+        setup_pins(); // Setup pins first.
+        self.clk = Clock::new_xosc_peri(xosc_ref);
+        self.timer = Timer::new(&self.clk);
+        self.dog = Watchdog::new(self.clk.ref_freq());
     }
     #[inline]
     fn is_ready(&self) -> bool {
@@ -165,12 +232,8 @@ pub fn pwm(p: PinID) -> PwmPin<Output> {
 
 #[inline]
 pub(super) fn write_reg(reg: *mut u32, v: u32, clear: bool) {
-    // NOTE(sf): This seems so weird, but it works lol.
-    //           See https://datasheets.raspberrypi.com/rp2040/rp2040-datasheet.pdf#atomic-rwtype
+    // NOTE(sf): See https://datasheets.raspberrypi.com/rp2040/rp2040-datasheet.pdf#atomic-rwtype
     unsafe {
-        write_volatile(
-            (reg as usize + if clear { 0x3000 } else { 0x2000 }) as *mut u32,
-            v,
-        )
+        if clear { atomic_clear(reg, v) } else { atomic_set(reg, v) }
     }
 }
@@ -25,14 +25,17 @@ use core::cell::UnsafeCell;
 use core::clone::Clone;
 use core::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use core::convert::{From, TryFrom};
+use core::default::Default;
 use core::fmt::{self, Debug, Formatter};
 use core::hint::unreachable_unchecked;
 use core::marker::{Copy, PhantomData};
+use core::matches;
 use core::option::Option::{self, None, Some};
 use core::result::Result::{self, Err, Ok};
 
 use crate::asm::nop;
-use crate::int::Acknowledge;
+use crate::dma::DmaWriter;
+use crate::int::{Acknowledge, Interrupt, wait_for_interrupt};
 use crate::pac::pads_bank0::GPIO;
 use crate::pac::{ADC, IO_BANK0, PADS_BANK0, RESETS, SIO, SYSCFG};
 use crate::pin::gpio::{Input, Output};
@@ -48,6 +51,7 @@ pub use self::boards::pins::*;
 pub mod adc;
 pub mod led;
 pub mod pwm;
+pub mod router;
 
 pub enum PinPull {
     Up,
@@ -59,6 +63,10 @@ pub enum PinSlew {
     Slow = 0u8,
     Fast = 1u8,
 }
+pub enum PinFilter {
+    DoubleSync,
+    Bypass,
+}
 #[repr(u8)]
 pub enum PinState {
     Low  = 0u8,
@@ -104,6 +112,7 @@ pub struct Pin<F: PinIO> {
     _p: PhantomData<UnsafeCell<F>>,
 }
 pub struct PinInvalidError;
+pub struct GpioPattern(u32);
 
 pub trait PinIO {
     const INPUT: bool;
@@ -186,6 +195,18 @@ impl PinID {
             .modify(|_, r| unsafe { r.funcsel().bits(f as u8) });
         self.ctrl().modify(|_, r| r.ie().bit(f as u8 != 0x1F))
     }
+    #[inline]
+    pub(super) fn set_pull(&self, p: PinPull) {
+        let (x, y) = p.sets();
+        self.ctrl().modify(|_, r| r.pue().bit(x).pde().bit(y))
+    }
+    #[inline]
+    pub(super) fn set_oe_inverted(&self, en: bool) {
+        unsafe { &*IO_BANK0::PTR }
+            .gpio(*self as usize)
+            .gpio_ctrl()
+            .modify(|_, r| if en { r.oeover().invert() } else { r.oeover().normal() });
+    }
 
     #[inline]
     fn mask(&self) -> u32 {
@@ -216,6 +237,12 @@ impl PinID {
         unsafe { &*PADS_BANK0::PTR }.gpio(*self as usize)
     }
     #[inline]
+    fn inter_clear(&self, i: PinInterrupt) {
+        unsafe { &*IO_BANK0::PTR }
+            .intr((*self as usize) / 8)
+            .write(|r| unsafe { r.bits((i as u32).unchecked_shl(self.offset() as u32)) })
+    }
+    #[inline]
     fn inter_set(&self, i: PinInterrupt, en: bool) {
         let (p, n) = (unsafe { &*IO_BANK0::PTR }, (*self as usize) / 8);
         write_reg(
@@ -304,6 +331,54 @@ impl Pin<Input> {
         self.i.ctrl().modify(|_, r| r.ie().bit(en))
     }
     #[inline]
+    pub fn wait_for_low(&self) {
+        if self.is_low() {
+            return;
+        }
+        self.wait_for_edge(PinInterrupt::Low)
+    }
+    #[inline]
+    pub fn wait_for_high(&self) {
+        if self.is_high() {
+            return;
+        }
+        self.wait_for_edge(PinInterrupt::High)
+    }
+    pub fn wait_for_edge(&self, edge: PinInterrupt) {
+        // NOTE(sf): WFI only wakes us if Bank0 is enabled in the NVIC, so we
+        //           spin instead if it isn't. If a Bank0 handler is set, it
+        //           must not clear this Pin's interrupt or we'll never see it.
+        let (e, w) = (self.interrupt_enabled(edge), Interrupt::Bank0.is_enabled());
+        self.interrupt_clear(edge);
+        self.interrupt_set(edge, true);
+        while !self.interrupt_status(edge) {
+            if w {
+                wait_for_interrupt();
+            } else {
+                nop();
+            }
+        }
+        self.interrupt_clear(edge);
+        if !e {
+            self.interrupt_set(edge, false);
+        }
+    }
+    #[inline]
+    pub fn input_filter(&self) -> PinFilter {
+        if unsafe { SYSCFG::steal() }.proc_in_sync_bypass().read().bits() & self.i.mask() != 0 {
+            PinFilter::Bypass
+        } else {
+            PinFilter::DoubleSync
+        }
+    }
+    #[inline]
+    pub fn set_input_filter(&self, f: PinFilter) {
+        // NOTE(sf): The RP2040 only has the two-stage synchronizer or a full
+        //           bypass, there's no single stage option. Bypass is only
+        //           safe if the input is already synchronous to clk_sys.
+        unsafe { self.set_sync_bypass(matches!(f, PinFilter::Bypass)) }
+    }
+    #[inline]
     pub fn into_output(self) -> Pin<Output> {
         Pin {
             i:  self.i.into_output(),
@@ -450,11 +525,26 @@ impl<F: PinIO> Pin<F> {
         !F::INPUT || (F::INPUT && self.i.is_odd())
     }
     #[inline]
+    pub fn slew(self, s: PinSlew) -> Pin<F> {
+        self.set_slew(s);
+        self
+    }
+    #[inline]
     pub fn pull(self, p: PinPull) -> Pin<F> {
         self.set_pull_type(p);
         self
     }
     #[inline]
+    pub fn drive(self, s: PinStrength) -> Pin<F> {
+        self.set_drive(s);
+        self
+    }
+    #[inline]
+    pub fn schmitt(self, en: bool) -> Pin<F> {
+        self.set_schmitt(en);
+        self
+    }
+    #[inline]
     pub fn set_pull_type(&self, p: PinPull) {
         let (x, y) = p.sets();
         self.i.ctrl().modify(|_, r| r.pue().bit(x).pde().bit(y))
@@ -478,10 +568,30 @@ impl<F: PinIO> Pin<F> {
         self.i.set_function(f)
     }
     #[inline]
+    pub fn get_function(&self) -> PinFunction {
+        match unsafe { &*IO_BANK0::PTR }
+            .gpio(self.i as usize)
+            .gpio_ctrl()
+            .read()
+            .funcsel()
+            .bits()
+        {
+            0x00 => PinFunction::JTag,
+            0x01 => PinFunction::Spi,
+            0x02 => PinFunction::Uart,
+            0x03 => PinFunction::I2c,
+            0x04 => PinFunction::Pwm,
+            0x05 => PinFunction::Sio,
+            0x06 => PinFunction::Pio0,
+            0x07 => PinFunction::Pio1,
+            0x08 => PinFunction::Clock,
+            0x09 => PinFunction::Usb,
+            _ => PinFunction::None,
+        }
+    }
+    #[inline]
     pub fn interrupt_clear(&self, i: PinInterrupt) {
-        unsafe { &*IO_BANK0::PTR }
-            .intr((self.i as usize) / 8)
-            .write(|r| unsafe { r.bits((i as u32).unchecked_shl(self.i.offset() as u32)) })
+        self.i.inter_clear(i)
     }
     #[inline]
     pub fn interrupt_set(&self, i: PinInterrupt, en: bool) {
@@ -517,6 +627,21 @@ impl<F: PinIO> Pin<F> {
         );
     }
 }
+impl GpioPattern {
+    #[inline]
+    pub fn new(_p: &Board, pins: &[PinID]) -> GpioPattern {
+        let mut m = 0u32;
+        for i in pins {
+            m |= i.into_output().mask();
+        }
+        GpioPattern(m)
+    }
+
+    #[inline]
+    pub fn mask(&self) -> u32 {
+        self.0
+    }
+}
 
 impl PinIO for Input {
     const INPUT: bool = true;
@@ -603,6 +728,19 @@ impl Clone for PinSlew {
     }
 }
 
+impl Copy for PinFilter {}
+impl Clone for PinFilter {
+    #[inline]
+    fn clone(&self) -> PinFilter {
+        *self
+    }
+}
+impl Default for PinFilter {
+    #[inline]
+    fn default() -> PinFilter {
+        PinFilter::DoubleSync
+    }
+}
 impl Copy for PinState {}
 impl Clone for PinState {
     #[inline]
@@ -659,6 +797,25 @@ impl<F: PinIO> Acknowledge for Pin<F> {
     }
 }
 
+impl DmaWriter<u32> for GpioPattern {
+    #[inline]
+    fn tx_req(&self) -> Option<u8> {
+        // NOTE(sf): SIO has no DREQ, so this must be paced with a DMA Timer
+        //           ('DmaPace::timer') or it will run as fast as the bus allows.
+        None
+    }
+    #[inline]
+    fn tx_info(&self) -> (u32, u32) {
+        // NOTE(sf): Each word replaces ALL of GPIO_OUT, so any other SIO Output
+        //           Pins not in the mask will be driven by the pattern too.
+        (unsafe { &*SIO::PTR }.gpio_out().as_ptr() as u32, u32::MAX)
+    }
+    #[inline]
+    fn tx_incremented(&self) -> bool {
+        false
+    }
+}
+
 impl Debug for PinInvalidError {
     #[cfg(feature = "debug")]
     #[inline]
@@ -672,6 +829,20 @@ impl Debug for PinInvalidError {
     }
 }
 
+// NOTE(sf): These are 'const' so pin choices can be checked at compile time, ie:
+//           'const _: () = assert!(valid_i2c(PinID::Pin0, PinID::Pin1));'
+#[inline]
+pub const fn valid_i2c(sda: PinID, scl: PinID) -> bool {
+    pins_i2c(&sda, &scl).is_some()
+}
+#[inline]
+pub const fn valid_spi(tx: PinID, sck: PinID, rx: Option<PinID>, cs: Option<PinID>) -> bool {
+    pins_spi(&tx, &sck, rx.as_ref(), cs.as_ref()).is_some()
+}
+#[inline]
+pub const fn valid_uart(tx: PinID, rx: PinID, cts: Option<PinID>, rts: Option<PinID>) -> bool {
+    pins_uart(&tx, &rx, cts.as_ref(), rts.as_ref()).is_some()
+}
 #[inline]
 pub fn emergency_pin_on(i: PinID) {
     let v = i.into_output().mask();
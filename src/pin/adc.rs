@@ -21,18 +21,31 @@
 
 extern crate core;
 
+use core::assert;
 use core::cell::UnsafeCell;
 use core::clone::Clone;
 use core::convert::{From, Into};
+#[cfg(feature = "pico")]
+use core::hint::unreachable_unchecked;
 use core::marker::{Copy, PhantomData};
-use core::option::Option::{self, Some};
+use core::mem::replace;
+use core::ops::FnMut;
+use core::option::Option::{self, None, Some};
 use core::result::Result::{self, Err, Ok};
 
+#[cfg(feature = "pico")]
+use crate::Board;
 use crate::asm::{delay, nop};
 use crate::atomic::{Mutex, with};
 use crate::clock::DIV;
-use crate::dma::{DmaReader, DmaWord};
+use crate::dma::mode::{Double, DoubleUp};
+use crate::dma::{Dma, DmaDouble, DmaMemMut, DmaReader, DmaSingle, DmaStream, DmaWord};
+use crate::int::Acknowledge;
 use crate::pac::{ADC, CLOCKS, IO_BANK0, RESETS};
+#[cfg(feature = "pico")]
+use crate::pac::SIO;
+#[cfg(feature = "cyw")]
+use crate::pin::PinFunction;
 use crate::pin::gpio::Input;
 use crate::pin::{Pin, PinID, PinInvalidError};
 use crate::static_instance;
@@ -54,16 +67,41 @@ pub struct AdcPin {
 }
 pub struct AdcFifo<R> {
     d:  ADC,
+    m:  u8,
+    c:  u8,
     _p: PhantomData<R>,
 }
+pub struct AdcCapture<'a> {
+    s: CaptureState<'a>,
+}
 pub struct AdcSelection(u8);
 pub struct AdcTempSensor(PhantomData<UnsafeCell<()>>);
 
 pub struct AdcFifoBuilder<R = u16> {
     d:  ADC,
+    t:  bool,
     _p: PhantomData<R>,
 }
 
+#[cfg(feature = "pico")]
+struct PinSaved {
+    i:    PinID,
+    oe:   bool,
+    out:  bool,
+    pad:  u32,
+    ctrl: u32,
+}
+
+enum CaptureState<'a> {
+    Empty,
+    Running(CaptureRunning<'a>),
+    Pending(CapturePending<'a>, DmaMemMut<'a, u16>),
+}
+
+type CaptureParts<'a> = (AdcFifo<u16>, &'a mut [u16], &'a mut [u16]);
+type CaptureRunning<'a> = DmaStream<DoubleUp<u16, AdcFifo<u16>, DmaMemMut<'a, u16>, DmaMemMut<'a, u16>>>;
+type CapturePending<'a> = DmaStream<Double<u16, AdcFifo<u16>, DmaMemMut<'a, u16>>>;
+
 pub trait AdcSelector {
     fn channel(&self) -> AdcChannel;
 }
@@ -97,6 +135,34 @@ impl AdcPin {
         Ok(AdcPin { i, _p: PhantomData })
     }
 
+    #[cfg(feature = "pico")]
+    pub fn read_vsys(p: &Board, vref_mv: u16) -> f32 {
+        // NOTE(sf): On the PicoW, Pin25 is the Cyw chip select and needs to be
+        //           driven high for the VSYS divider to be readable on Pin29,
+        //           which is also the Cyw gSPI clock. Both Pins are restored
+        //           after, but this can't be used during a Cyw transfer.
+        #[cfg(feature = "cyw")]
+        let c = PinSaved::new(PinID::Pin25);
+        let v = PinSaved::new(PinID::Pin29);
+        #[cfg(feature = "cyw")]
+        {
+            let s = unsafe { &*SIO::PTR };
+            s.gpio_out_set().write(|r| unsafe { r.bits(PinID::Pin25.mask()) });
+            PinID::Pin25.set_output();
+            PinID::Pin25.set_function(PinFunction::Sio);
+        }
+        let a = match AdcPin::new(Pin::get(p, PinID::Pin29).into_input()) {
+            Ok(v) => v,
+            Err(_) => unsafe { unreachable_unchecked() }, // Pin29 is always ADC3.
+        };
+        // VSYS is fed through a 1/3 divider.
+        let r = a.read_block() as f32 * 3f32 * (vref_mv as f32 / 1000f32) / 4096f32;
+        v.restore();
+        #[cfg(feature = "cyw")]
+        c.restore();
+        r
+    }
+
     #[inline]
     pub fn wait_ready(&self) {
         let d = unsafe { ADC::steal() };
@@ -129,6 +195,27 @@ impl AdcPin {
         self.wait_ready();
         self.read()
     }
+    pub fn capture(&mut self, ch: Dma, buf: &mut [u16]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let n = buf.len();
+        let mut f = AdcFifoBuilder::new().channel(self).dma().start_paused(true);
+        f.clear();
+        let d = DmaSingle::new(ch, &f, DmaMemMut::new(buf)).start();
+        self.start_free_running();
+        d.wait();
+        // NOTE(sf): Not using 'AdcFifo::close' here as that also resets the
+        //           clock divider. Anything converted after the DMA finished
+        //           is dropped.
+        f.pause();
+        while !f.is_ready() {
+            nop();
+        }
+        f.clear();
+        f.d.fcs().modify(|_, r| r.en().clear_bit().dreq_en().clear_bit());
+        n
+    }
     #[inline]
     pub fn stop_free_running(&mut self) {
         self.set_free_running(false)
@@ -159,6 +246,19 @@ impl AdcFifo<u16> {
     pub fn read(&mut self) -> u16 {
         self.d.fifo().read().val().bits()
     }
+    #[inline]
+    pub fn read_tagged(&mut self) -> (AdcChannel, u16) {
+        // NOTE(sf): We can't ask the hardware which channel a FIFO entry came
+        //           from, so this follows the same order the round-robin scan
+        //           does. This only stays correct if every sample is read
+        //           through here (in lock-step) and the FIFO never overflows.
+        let v = self.d.fifo().read().val().bits();
+        let i = self.c;
+        if self.m != 0 {
+            self.c = next_channel(self.m, self.c);
+        }
+        (channel_from(i), v)
+    }
 }
 impl AdcTempSensor {
     #[inline]
@@ -167,12 +267,22 @@ impl AdcTempSensor {
     }
     #[inline]
     pub fn read(&self) -> u8 {
+        // NOTE(sf): The result is 12 bits, so keep the top 8 instead of the
+        //           noisy bottom ones.
+        unsafe { self.read_raw().unchecked_shr(4) as u8 }
+    }
+    #[inline]
+    pub fn read_raw(&self) -> u16 {
         self.wait_ready();
         let d = unsafe { ADC::steal() };
         d.cs()
             .modify(|_, r| unsafe { r.ainsel().bits(AdcChannel::Chan4 as u8).start_once().set_bit() });
         self.wait_ready();
-        d.result().read().result().bits() as u8
+        d.result().read().result().bits()
+    }
+    #[inline]
+    pub fn read_celsius(&self, vref_mv: u16) -> f32 {
+        temp_celsius(self.read_raw(), vref_mv)
     }
     #[inline]
     pub fn wait_ready(&self) {
@@ -200,7 +310,7 @@ impl<R> AdcFifo<R> {
             nop();
         }
         while self.len() > 0 {
-            let _ = self.d.result().read().result().bits();
+            let _ = self.d.fifo().read();
         }
         self.d
             .fcs()
@@ -209,8 +319,9 @@ impl<R> AdcFifo<R> {
     }
     #[inline]
     pub fn clear(&mut self) {
+        // NOTE(sf): Reading RESULT doesn't pop the FIFO.
         while self.len() > 0 {
-            let _ = self.read_sample();
+            let _ = self.d.fifo().read();
         }
     }
     #[inline]
@@ -230,6 +341,14 @@ impl<R> AdcFifo<R> {
         self.d.cs().modify(|_, r| r.start_once().set_bit())
     }
     #[inline]
+    pub fn set_interrupt(&mut self, en: bool) {
+        self.d.inte().modify(|_, r| r.fifo().bit(en))
+    }
+    #[inline]
+    pub fn is_interrupt_pending(&self) -> bool {
+        self.d.ints().read().fifo().bit_is_set()
+    }
+    #[inline]
     pub fn wait_interrupt(&self) {
         while self.d.intr().read().fifo().bit_is_clear() {
             nop();
@@ -268,11 +387,77 @@ impl<R> AdcFifo<R> {
         self.d.cs().modify(|_, r| r.start_many().bit(!paused))
     }
 }
+impl<'a> AdcCapture<'a> {
+    #[inline]
+    pub fn new(f: AdcFifo<u16>, ch1: Dma, ch2: Dma, first: &'a mut [u16], second: &'a mut [u16]) -> AdcCapture<'a> {
+        // NOTE(sf): 'f' should've been built with '.dma()' so the FIFO raises
+        //           DREQs, otherwise nothing will move.
+        //           No ring is set, so 'write_next' won't refuse the buffer.
+        let d = DmaDouble::new(ch1, ch2, f, DmaMemMut::new(first)).start();
+        AdcCapture {
+            s: match d.write_next(DmaMemMut::new(second)) {
+                Ok(v) => CaptureState::Running(v),
+                Err((d, b)) => CaptureState::Pending(d, b),
+            },
+        }
+    }
+
+    pub fn close(mut self) -> Option<CaptureParts<'a>> {
+        match replace(&mut self.s, CaptureState::Empty) {
+            CaptureState::Running(d) => {
+                let (f, a, b) = d.abort();
+                Some((f, a.into_inner(), b.into_inner()))
+            },
+            CaptureState::Pending(d, b) => {
+                let (f, a) = d.abort();
+                Some((f, a.into_inner(), b.into_inner()))
+            },
+            CaptureState::Empty => None,
+        }
+    }
+    pub fn poll(&mut self) -> Option<&[u16]> {
+        // NOTE(sf): The buffer we handed out last time is re-armed first, so
+        //           it's only safe to use the returned slice until the next
+        //           call to 'poll'. The FIFO is 4 samples deep, so the caller
+        //           has until the active buffer fills up (plus 4 samples) to
+        //           come back here without dropping anything.
+        if let CaptureState::Pending(..) = self.s {
+            if let CaptureState::Pending(d, b) = replace(&mut self.s, CaptureState::Empty) {
+                self.s = match d.write_next(b) {
+                    Ok(v) => CaptureState::Running(v),
+                    Err((d, b)) => CaptureState::Pending(d, b),
+                };
+            }
+        }
+        match &self.s {
+            CaptureState::Running(d) if d.is_done() => (),
+            _ => return None,
+        }
+        if let CaptureState::Running(d) = replace(&mut self.s, CaptureState::Empty) {
+            let (b, n) = d.wait_output();
+            self.s = CaptureState::Pending(n, b);
+        }
+        match &self.s {
+            CaptureState::Pending(_, b) => Some(b.as_slice()),
+            _ => None,
+        }
+    }
+    pub fn run<F: FnMut(&[u16]) -> bool>(&mut self, mut f: F) {
+        loop {
+            match self.poll() {
+                Some(b) if !f(b) => break,
+                Some(_) => continue,
+                None => nop(),
+            }
+        }
+    }
+}
 impl AdcFifoBuilder<u16> {
     #[inline]
     pub fn new() -> AdcFifoBuilder<u16> {
         AdcFifoBuilder {
             d:  unsafe { ADC::steal() },
+            t:  false,
             _p: PhantomData,
         }
     }
@@ -280,7 +465,16 @@ impl AdcFifoBuilder<u16> {
     #[inline]
     pub fn shift(self) -> AdcFifoBuilder<u8> {
         self.d.fcs().modify(|_, r| r.shift().set_bit());
-        AdcFifoBuilder { d: self.d, _p: PhantomData }
+        AdcFifoBuilder {
+            d:  self.d,
+            t:  self.t,
+            _p: PhantomData,
+        }
+    }
+    #[inline]
+    pub fn with_channel_ids(mut self) -> AdcFifoBuilder<u16> {
+        self.t = true;
+        self
     }
 }
 impl<R> AdcFifoBuilder<R> {
@@ -308,9 +502,16 @@ impl<R> AdcFifoBuilder<R> {
     }
     #[inline]
     pub fn start_paused(self, paused: bool) -> AdcFifo<R> {
+        let v = self.d.cs().read();
+        let (m, c) = (if self.t { v.rrobin().bits() } else { 0 }, v.ainsel().bits());
         self.d.fcs().modify(|_, r| r.en().set_bit());
         self.d.cs().modify(|_, r| r.start_once().bit(!paused));
-        AdcFifo { d: self.d, _p: PhantomData }
+        AdcFifo {
+            m,
+            c,
+            d: self.d,
+            _p: PhantomData,
+        }
     }
     #[inline]
     pub fn channel(self, pin: &AdcPin) -> AdcFifoBuilder<R> {
@@ -325,6 +526,41 @@ impl<R> AdcFifoBuilder<R> {
     }
 }
 
+#[cfg(feature = "pico")]
+impl PinSaved {
+    #[inline]
+    fn new(i: PinID) -> PinSaved {
+        let s = unsafe { &*SIO::PTR };
+        PinSaved {
+            i,
+            oe: s.gpio_oe().read().bits() & i.mask() != 0,
+            out: s.gpio_out().read().bits() & i.mask() != 0,
+            pad: i.ctrl().read().bits(),
+            ctrl: unsafe { &*IO_BANK0::PTR }.gpio(i as usize).gpio_ctrl().read().bits(),
+        }
+    }
+
+    #[inline]
+    fn restore(self) {
+        let (s, m) = (unsafe { &*SIO::PTR }, self.i.mask());
+        if self.out {
+            s.gpio_out_set().write(|r| unsafe { r.bits(m) });
+        } else {
+            s.gpio_out_clr().write(|r| unsafe { r.bits(m) });
+        }
+        if self.oe {
+            s.gpio_oe_set().write(|r| unsafe { r.bits(m) });
+        } else {
+            s.gpio_oe_clr().write(|r| unsafe { r.bits(m) });
+        }
+        self.i.ctrl().write(|r| unsafe { r.bits(self.pad) });
+        unsafe { &*IO_BANK0::PTR }
+            .gpio(self.i as usize)
+            .gpio_ctrl()
+            .write(|r| unsafe { r.bits(self.ctrl) });
+    }
+}
+
 impl AdcSelector for AdcPin {
     #[inline]
     fn channel(&self) -> AdcChannel {
@@ -377,6 +613,21 @@ impl<A: AdcSelector, B: AdcSelector, C: AdcSelector, D: AdcSelector, E: AdcSelec
     }
 }
 
+impl<R> Acknowledge for AdcFifo<R> {
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        // NOTE(sf): The FIFO interrupt is a level and stays set until the FIFO
+        //           drops under 'thresh', so we mask it here to stop it from
+        //           firing again. Read the samples then re-enable it with
+        //           'set_interrupt'.
+        if !self.is_interrupt_pending() {
+            return false;
+        }
+        self.set_interrupt(false);
+        true
+    }
+}
+
 impl<R: DmaWord> DmaReader<R> for AdcFifo<R> {
     #[inline]
     fn rx_req(&self) -> Option<u8> {
@@ -420,6 +671,51 @@ impl<R: DmaWord> DmaReader<R> for &mut AdcFifo<R> {
     }
 }
 
+#[inline]
+fn channel_from(v: u8) -> AdcChannel {
+    match v {
+        0 => AdcChannel::Chan0,
+        1 => AdcChannel::Chan1,
+        2 => AdcChannel::Chan2,
+        3 => AdcChannel::Chan3,
+        _ => AdcChannel::Chan4,
+    }
+}
+#[inline]
+fn next_channel(m: u8, c: u8) -> u8 {
+    // Same as the hardware, move to the next set bit above the current
+    // channel in the round-robin mask, wrapping back to the bottom.
+    for i in 1..=5u8 {
+        let n = (c + i) % 5;
+        if m & unsafe { 1u8.unchecked_shl(n as u32) } != 0 {
+            return n;
+        }
+    }
+    c
+}
+#[inline]
+const fn temp_celsius(raw: u16, vref_mv: u16) -> f32 {
+    // Datasheet 4.9.5: T = 27 - (ADC_voltage - 0.706) / 0.001721
+    let v = (raw & 0xFFF) as f32 * (vref_mv as f32 / 1000f32) / 4096f32;
+    27f32 - (v - 0.706f32) / 0.001721f32
+}
+
+const _: () = {
+    // With a 4.096V reference each code is exactly 1mV.
+    let v = temp_celsius(706, 4096);
+    assert!(v > 26.99f32 && v < 27.01f32);
+    let v = temp_celsius(534, 4096);
+    assert!(v > 126.9f32 && v < 127.0f32);
+    let v = temp_celsius(0, 4096);
+    assert!(v > 437.2f32 && v < 437.3f32);
+    // 0.706V is ~876 at 3.3V.
+    let v = temp_celsius(876, 3300);
+    assert!(v > 26.5f32 && v < 27.5f32);
+    // Only the low 12 bits are the result.
+    assert!(temp_celsius(0xF000 | 876, 3300) == temp_celsius(876, 3300));
+    assert!(temp_celsius(877, 3300) < temp_celsius(876, 3300));
+};
+
 fn prepare_adc() {
     with(|x| {
         let v = READY.borrow_mut(x);
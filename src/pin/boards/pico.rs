@@ -64,7 +64,7 @@ pub enum PinID {
 }
 
 #[inline]
-pub(crate) fn pins_pwm(pin: &PinID) -> PwmID {
+pub(crate) const fn pins_pwm(pin: &PinID) -> PwmID {
     match pin {
         PinID::Pin0 => PwmID::Pwm0A,
         PinID::Pin1 => PwmID::Pwm0B,
@@ -99,7 +99,7 @@ pub(crate) fn pins_pwm(pin: &PinID) -> PwmID {
     }
 }
 #[inline]
-pub(crate) fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {
+pub(crate) const fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {
     let d = match sda {
         PinID::Pin0 => I2cID::I2C0,
         PinID::Pin2 => I2cID::I2C1,
@@ -135,7 +135,7 @@ pub(crate) fn pins_i2c(sda: &PinID, scl: &PinID) -> Option<I2cID> {
     Some(d)
 }
 #[inline]
-pub(crate) fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&PinID>) -> Option<SpiID> {
+pub(crate) const fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&PinID>) -> Option<SpiID> {
     let d = match tx {
         PinID::Pin3 => SpiID::Spi0,
         PinID::Pin7 => SpiID::Spi0,
@@ -182,7 +182,7 @@ pub(crate) fn pins_spi(tx: &PinID, sck: &PinID, rx: Option<&PinID>, cs: Option<&
     Some(d)
 }
 #[inline]
-pub(crate) fn pins_uart(tx: &PinID, rx: &PinID, cts: Option<&PinID>, rts: Option<&PinID>) -> Option<UartID> {
+pub(crate) const fn pins_uart(tx: &PinID, rx: &PinID, cts: Option<&PinID>, rts: Option<&PinID>) -> Option<UartID> {
     let d = match tx {
         PinID::Pin0 => UartID::Uart0,
         PinID::Pin4 => UartID::Uart1,
@@ -21,16 +21,32 @@
 
 extern crate core;
 
+use core::clone::Clone;
 use core::convert::{From, Into};
+use core::option::Option::{None, Some};
 use core::ops::{Deref, DerefMut};
 
 use crate::Board;
+use crate::clock::Timer;
 use crate::pin::gpio::Output;
-use crate::pin::pwm::PwmPin;
+use crate::pin::pwm::{PwmGroup, PwmPin, gamma_scale};
 use crate::pin::{Pin, PinID};
 
 pub struct Led(Pin<Output>);
 pub struct LedPwm(PwmPin<Output>);
+pub struct RgbLed {
+    r: PwmPin<Output>,
+    g: PwmPin<Output>,
+    b: PwmPin<Output>,
+}
+pub struct Blinker<'a> {
+    t:    Timer,
+    pin:  Pin<Output>,
+    pat:  &'a [u16],
+    idx:  usize,
+    per:  u64,
+    last: u64,
+}
 
 impl Led {
     #[inline]
@@ -66,6 +82,97 @@ impl LedPwm {
         self.0.set_duty((self.0.get_max_duty() / 100) * (p as u16))
     }
 }
+impl RgbLed {
+    #[inline]
+    pub fn get(p: &Board, r: PinID, g: PinID, b: PinID) -> RgbLed {
+        RgbLed::new(
+            Pin::get(p, r).into_pwm(),
+            Pin::get(p, g).into_pwm(),
+            Pin::get(p, b).into_pwm(),
+        )
+    }
+    pub fn new(r: PwmPin<Output>, g: PwmPin<Output>, b: PwmPin<Output>) -> RgbLed {
+        // NOTE(sf): All three use the top of the red channel and are restarted
+        //           together so they stay phase-aligned. Channels that share a
+        //           slice are already aligned. The clock dividers aren't
+        //           touched, so they should match.
+        let t = r.get_top();
+        g.set_top(t);
+        b.set_top(t);
+        PwmGroup::new().add(r.id()).add(g.id()).add(b.id()).enable_all();
+        RgbLed { r, g, b }
+    }
+
+    #[inline]
+    pub fn off(&self) {
+        self.set_color(0, 0, 0)
+    }
+    #[inline]
+    pub fn set_color(&self, r: u8, g: u8, b: u8) {
+        let m = self.r.get_max_duty() as u32;
+        self.r.set_duty(((r as u32 * m) / 0xFF) as u16);
+        self.g.set_duty(((g as u32 * m) / 0xFF) as u16);
+        self.b.set_duty(((b as u32 * m) / 0xFF) as u16);
+    }
+    #[inline]
+    pub fn set_color_gamma(&self, r: u8, g: u8, b: u8) {
+        let m = self.r.get_max_duty();
+        self.r.set_duty(gamma_scale(r, m));
+        self.g.set_duty(gamma_scale(g, m));
+        self.b.set_duty(gamma_scale(b, m));
+    }
+    #[inline]
+    pub fn into_inner(self) -> (PwmPin<Output>, PwmPin<Output>, PwmPin<Output>) {
+        (self.r, self.g, self.b)
+    }
+}
+impl<'a> Blinker<'a> {
+    #[inline]
+    pub fn new(pin: Pin<Output>, t: &Timer, period_ms: u32) -> Blinker<'a> {
+        Blinker {
+            t:    t.clone(),
+            pat:  &[],
+            idx:  0usize,
+            per:  period_ms as u64 * 1_000,
+            last: t.current_tick(),
+            pin,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        let n = self.t.current_tick();
+        let d = match self.pat.get(self.idx) {
+            Some(v) => *v as u64 * 1_000,
+            None => self.per,
+        };
+        if n.saturating_sub(self.last) < d {
+            return;
+        }
+        self.pin.toggle();
+        self.last = n;
+        if !self.pat.is_empty() {
+            self.idx = (self.idx + 1) % self.pat.len();
+        }
+    }
+    #[inline]
+    pub fn into_pin(self) -> Pin<Output> {
+        self.pin
+    }
+    #[inline]
+    pub fn set_period_ms(&mut self, ms: u32) {
+        self.per = ms as u64 * 1_000;
+        self.pat = &[];
+    }
+    // NOTE(sf): Patterns are alternating on and off times in milliseconds, starting
+    //           with on. An odd length pattern will flip on each repeat.
+    #[inline]
+    pub fn set_pattern(&mut self, p: &'a [u16]) {
+        self.pat = p;
+        self.idx = 0;
+        self.last = self.t.current_tick();
+        self.pin.high();
+    }
+}
 
 impl Deref for Led {
     type Target = Pin<Output>;
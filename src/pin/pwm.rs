@@ -24,7 +24,9 @@ extern crate core;
 use core::cell::UnsafeCell;
 use core::clone::Clone;
 use core::marker::{Copy, PhantomData};
+use core::option::Option::{self, Some};
 
+use crate::dma::DmaWriter;
 use crate::int::Acknowledge;
 use crate::pac::PWM;
 use crate::pac::pwm::CH;
@@ -32,6 +34,9 @@ use crate::pin::PinIO;
 use crate::pin::gpio::{Input, Output};
 use crate::write_reg;
 
+// Brightness (0 - 255) to duty (0 - 0xFFFF) with a gamma of 2.2.
+static GAMMA: [u16; 256] = gamma_table();
+
 #[repr(u8)]
 pub enum PwmID {
     Pwm0A = 0x00u8,
@@ -64,6 +69,9 @@ pub struct PwmPin<F: PinIO> {
     _p: PhantomData<F>,
 }
 
+pub struct PwmGroup(u8);
+pub struct PwmSliceTarget<'a>(&'a PwmPin<Output>);
+
 pub type PwmInput = PwmPin<Input>;
 pub type PwmOutput = PwmPin<Output>;
 
@@ -201,6 +209,10 @@ impl PwmPin<Output> {
             self.low();
         }
     }
+    #[inline]
+    pub fn set_brightness(&self, level: u8) {
+        self.set_duty(gamma_scale(level, self.get_max_duty()))
+    }
 }
 impl<F: PinIO> PwmPin<F> {
     #[inline]
@@ -305,6 +317,77 @@ impl<F: PinIO> PwmPin<F> {
     }
 }
 
+impl<'a> PwmSliceTarget<'a> {
+    #[inline]
+    pub fn new(p: &'a PwmPin<Output>) -> PwmSliceTarget<'a> {
+        // NOTE(sf): IO registers ignore the access width, so there's no way to
+        //           DMA into just one half of CC. This streams into the whole
+        //           slice, the A and B channels both get every value and any
+        //           duty set on the sibling pin will be overwritten.
+        PwmSliceTarget(p)
+    }
+
+    #[inline]
+    pub fn pin(&self) -> &PwmPin<Output> {
+        self.0
+    }
+}
+impl PwmGroup {
+    #[inline]
+    pub const fn new() -> PwmGroup {
+        PwmGroup(0u8)
+    }
+
+    #[inline]
+    pub fn add(mut self, i: &PwmID) -> PwmGroup {
+        self.0 |= unsafe { 1u8.unchecked_shl(*i as u32 & 0xF) };
+        self
+    }
+    #[inline]
+    pub fn mask(&self) -> u8 {
+        self.0
+    }
+    #[inline]
+    pub fn remove(mut self, i: &PwmID) -> PwmGroup {
+        self.0 &= !unsafe { 1u8.unchecked_shl(*i as u32 & 0xF) };
+        self
+    }
+    #[inline]
+    pub fn contains(&self, i: &PwmID) -> bool {
+        self.0 & unsafe { 1u8.unchecked_shl(*i as u32 & 0xF) } != 0
+    }
+    #[inline]
+    pub fn enable_all(&self) {
+        // NOTE(sf): This only starts and stops the slice counters. It doesn't
+        //           go through any 'PwmPin', so their 'is_enabled'/'get_state'
+        //           won't reflect it. Once a pin's slice is in a group, only
+        //           toggle it via the group and use 'set_duty' on the pin.
+        if self.0 == 0 {
+            return;
+        }
+        let p = unsafe { &*PWM::ptr() };
+        // NOTE(sf): Stop the selected slices first so the counter resets below
+        //           stay put until they're all released together.
+        write_reg(p.en().as_ptr(), self.0 as u32, true);
+        for i in 0..8 {
+            if self.0 & unsafe { 1u8.unchecked_shl(i as u32) } == 0 {
+                continue;
+            }
+            p.ch(i).ctr().write(|r| unsafe { r.ctr().bits(0) });
+        }
+        // NOTE(sf): The EN register aliases every slice's CSR_EN bit, so
+        //           a single set write starts all of them on the same cycle.
+        write_reg(p.en().as_ptr(), self.0 as u32, false);
+    }
+    #[inline]
+    pub fn disable_all(&self) {
+        if self.0 == 0 {
+            return;
+        }
+        write_reg(unsafe { (*PWM::ptr()).en().as_ptr() }, self.0 as u32, true);
+    }
+}
+
 impl Copy for PwmID {}
 impl Clone for PwmID {
     #[inline]
@@ -313,6 +396,31 @@ impl Clone for PwmID {
     }
 }
 
+impl Copy for PwmGroup {}
+impl Clone for PwmGroup {
+    #[inline]
+    fn clone(&self) -> PwmGroup {
+        PwmGroup(self.0)
+    }
+}
+
+impl<'a> DmaWriter<u16> for PwmSliceTarget<'a> {
+    #[inline]
+    fn tx_req(&self) -> Option<u8> {
+        Some(0x18 + (self.0.i as u8 & 0xF))
+    }
+    #[inline]
+    fn tx_info(&self) -> (u32, u32) {
+        // NOTE(sf): Narrow writes are replicated across the whole register by
+        //           the bus, see the NOTE in 'PwmSliceTarget::new'.
+        (self.0.i.reg().cc().as_ptr() as u32, u32::MAX)
+    }
+    #[inline]
+    fn tx_incremented(&self) -> bool {
+        false
+    }
+}
+
 impl<F: PinIO> Acknowledge for PwmPin<F> {
     #[inline]
     fn ack_interrupt(&mut self) -> bool {
@@ -321,3 +429,30 @@ impl<F: PinIO> Acknowledge for PwmPin<F> {
         r
     }
 }
+
+#[inline]
+pub(super) fn gamma_scale(level: u8, max: u16) -> u16 {
+    ((GAMMA[level as usize] as u32 * max as u32) / 0xFFFF) as u16
+}
+
+const fn gamma_table() -> [u16; 256] {
+    // NOTE(sf): (i / 255) ^ 2.2 is done as the fifth root of (i / 255) ^ 11
+    //           since 'powf' isn't usable in const.
+    let (mut r, mut i) = ([0u16; 256], 1usize);
+    while i < 256 {
+        let (x, mut a, mut n) = (i as f64 / 255f64, 1f64, 0);
+        while n < 11 {
+            a *= x;
+            n += 1;
+        }
+        let (mut y, mut n) = (1f64, 0);
+        while n < 128 {
+            let k = y * y * y * y;
+            y -= (k * y - a) / (5f64 * k);
+            n += 1;
+        }
+        r[i] = (y * 65535f64 + 0.5f64) as u16;
+        i += 1;
+    }
+    r
+}
@@ -0,0 +1,104 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::iter::Iterator;
+use core::option::Option::{self, None, Some};
+
+use crate::int::{Acknowledge, Interrupt, Interrupted};
+use crate::pac::IO_BANK0;
+use crate::pin::{PinID, PinInterrupt, on_core0};
+
+pub struct PinRouter<const N: usize> {
+    e: [Option<Route>; N],
+}
+
+struct Route {
+    pin:  PinID,
+    edge: u8,
+    func: fn(),
+}
+
+impl<const N: usize> PinRouter<N> {
+    #[inline]
+    pub const fn new() -> PinRouter<N> {
+        PinRouter { e: [const { None }; N] }
+    }
+
+    #[inline]
+    pub fn off(&mut self, pin: PinID) {
+        for i in self.e.iter_mut() {
+            if i.as_ref().is_some_and(|r| r.pin == pin) {
+                pin.inter_set(PinInterrupt::All, false);
+                *i = None;
+            }
+        }
+    }
+    pub fn on(&mut self, pin: PinID, edge: PinInterrupt, func: fn()) -> bool {
+        // Replace any existing route for this pin first.
+        let v = match self.e.iter_mut().find(|i| i.as_ref().is_some_and(|r| r.pin == pin)) {
+            Some(v) => v,
+            None => match self.e.iter_mut().find(|i| i.is_none()) {
+                Some(v) => v,
+                None => return false,
+            },
+        };
+        pin.inter_set(PinInterrupt::All, false);
+        *v = Some(Route { pin, edge: edge as u8, func });
+        pin.inter_clear(PinInterrupt::All);
+        pin.inter_set(edge, true);
+        true
+    }
+    pub fn dispatch(&mut self) -> bool {
+        let (p, c) = (unsafe { &*IO_BANK0::PTR }, on_core0());
+        let mut f = false;
+        for i in self.e.iter().flatten() {
+            let n = (i.pin as usize) / 8;
+            let r = if c { p.proc0_ints(n).read().bits() } else { p.proc1_ints(n).read().bits() };
+            let v = unsafe { r.unchecked_shr(i.pin.offset() as u32) } as u8 & i.edge;
+            if v == 0 {
+                continue;
+            }
+            // NOTE(sf): Only the edge bits latch and can be cleared, level
+            //           interrupts keep firing until the level changes.
+            p.intr(n).write(|r| unsafe { r.bits((v as u32).unchecked_shl(i.pin.offset() as u32)) });
+            (i.func)();
+            f = true;
+        }
+        f
+    }
+}
+
+impl<const N: usize> Acknowledge for PinRouter<N> {
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        self.dispatch()
+    }
+}
+impl<const N: usize> Interrupted for PinRouter<N> {
+    #[inline]
+    fn interrupt(&mut self, i: Interrupt) {
+        if i == Interrupt::Bank0 {
+            self.dispatch();
+        }
+    }
+}
@@ -21,36 +21,59 @@
 
 extern crate core;
 
+use core::assert;
 use core::cell::UnsafeCell;
 use core::clone::Clone;
 use core::cmp::Ord;
+use core::convert::From;
 use core::fmt::{self, Debug, Formatter};
 use core::iter::Iterator;
 use core::marker::{Copy, PhantomData, Send};
+use core::matches;
 use core::ops::{Deref, DerefMut, Drop, FnOnce};
 use core::option::Option::{self, None, Some};
+use core::panic;
 use core::ptr::{read_volatile, write_volatile};
 use core::result::Result::{self, Err, Ok};
 
 use crate::asm::nop;
+use crate::atomic::{Mutex, with};
 use crate::pac::pio0::{RegisterBlock, SM};
 use crate::pac::{PIO0, PIO1, RESETS};
 use crate::pin::{PinDirection, PinID, PinState};
 use crate::pio::state::{Running, Stopped, Uninit};
 use crate::{Board, write_reg};
 
+mod asm;
 mod config;
+mod encoder;
 mod group;
+mod i2c;
 mod int;
 mod io;
+mod ws2812;
 
+pub use self::asm::*;
 pub use self::config::*;
+pub use self::encoder::*;
 pub use self::group::*;
+pub use self::i2c::*;
 pub use self::int::*;
 pub use self::io::*;
+pub use self::ws2812::*;
 
 pub const MAX_INSTRUCTIONS: usize = 32usize;
 
+const EXEC_ATTEMPTS: u32 = 0xFFFFu32;
+
+// NOTE(sf): Bitmask of claimed StateMachines across both PIO blocks, the lower
+//           4 bits are PIO0 and the upper 4 bits are PIO1.
+static STATES: Mutex<u8> = Mutex::new(0u8);
+// NOTE(sf): Bitmask of used instruction memory for PIO0 and PIO1. This isn't
+//           kept in 'Pio' so a driver that only holds a State can still
+//           uninstall its program when it's dropped.
+static PROGRAMS: Mutex<[u32; 2]> = Mutex::new([0u32; 2]);
+
 #[repr(u8)]
 pub enum Slot {
     Index0 = 0u8,
@@ -63,15 +86,23 @@ pub enum PioID {
     Pio1,
 }
 pub enum PioError {
+    InUse,
     TooLarge,
     WouldBlock,
     InvalidProgram,
+    InvalidPins,
 }
 
+pub struct PioAllocator;
 pub struct Pio {
-    sm:   UnsafeCell<u8>,
-    dev:  *const RegisterBlock,
-    used: u32,
+    sm:  UnsafeCell<u8>,
+    dev: *const RegisterBlock,
+}
+pub struct FifoDebug {
+    pub tx_over:  bool,
+    pub tx_stall: bool,
+    pub rx_under: bool,
+    pub rx_stall: bool,
 }
 pub struct Handle {
     src:    u8,
@@ -126,13 +157,30 @@ impl Pio {
                 PIO1::ptr()
             },
         };
+        // The block was just reset, so nothing is installed anymore.
+        with(|x| PROGRAMS.borrow_mut(x)[index(v)] = 0u32);
         Pio {
-            sm:   UnsafeCell::new(0u8),
-            dev:  v,
-            used: 0u32,
+            sm:  UnsafeCell::new(0u8),
+            dev: v,
         }
     }
 
+    pub fn close(self) {
+        // Stop all the SMs before holding the block in reset.
+        self.ptr().ctrl().write(|r| unsafe { r.sm_enable().bits(0) });
+        let (i, m) = (self.id(), self.sm.into_inner());
+        for x in 0..4u8 {
+            if m & unsafe { 1u8.unchecked_shl(x as u32) } != 0 {
+                PioAllocator::release(i, Slot::from(x));
+            }
+        }
+        with(|x| PROGRAMS.borrow_mut(x)[index(self.dev)] = 0u32);
+        let r = unsafe { RESETS::steal() };
+        match i {
+            PioID::Pio0 => r.reset().modify(|_, r| r.pio0().set_bit()),
+            PioID::Pio1 => r.reset().modify(|_, r| r.pio1().set_bit()),
+        };
+    }
     #[inline]
     pub fn irq_flags(&self) -> u8 {
         self.ptr().irq().read().irq().bits()
@@ -158,52 +206,54 @@ impl Pio {
         Interrupt::new(self, i)
     }
     #[inline]
-    pub fn release<'a, S: PioStateDone>(&mut self, i: State<'a, S>) {
-        unsafe { *self.sm.get() &= 1u8.unchecked_shl(i.m.idx as u32) }
+    pub fn id(&self) -> PioID {
+        if self.dev == PIO0::PTR { PioID::Pio0 } else { PioID::Pio1 }
+    }
+    #[inline]
+    pub fn release<'a, S: PioStateDone>(&self, i: State<'a, S>) {
+        self.unclaim(i.m.idx)
     }
     #[inline]
     pub fn state<'a>(&'a self, i: Slot) -> Option<State<'a, Uninit>> {
-        unsafe {
-            if *self.sm.get() & 1u8.unchecked_shl(i as u32) != 0 {
-                return None;
-            }
-            *self.sm.get() |= 1u8.unchecked_shl(i as u32);
+        if !self.claim(i) {
+            return None;
         }
-        Some(State {
-            m:  Machine {
-                _p:  PhantomData,
-                sm:  self.ptr().sm(i as usize) as *const SM,
-                idx: i,
-                pio: self.dev,
-            },
-            _p: PhantomData,
-        })
+        Some(unsafe { self.state_unsafe(i) })
     }
-    pub fn install<const N: usize>(&mut self, p: &Program<N>) -> Result<Handle, PioError> {
-        if p.len == 0 {
-            return Err(PioError::InvalidProgram);
-        }
-        let n = (p.len as usize).min(N);
-        if n > MAX_INSTRUCTIONS {
-            return Err(PioError::TooLarge);
+    pub fn install_state<'a, const N: usize>(&'a self, p: &Program<N>, i: Slot) -> Result<(Handle, State<'a, Uninit>), PioError> {
+        // NOTE(sf): Claim the State before installing, so a busy Slot doesn't
+        //           leave the Program in instruction memory with no Handle.
+        if !self.claim(i) {
+            return Err(PioError::InUse);
         }
-        let c = unsafe { p.code.get_unchecked(0..n) };
-        let (s, m) = match p.start {
-            Some(v) => self.try_install_at(v, c).map(|r| (v, r)),
-            None => self.try_install(c),
+        match self.install(p) {
+            Ok(h) => Ok((h, unsafe { self.state_unsafe(i) })),
+            Err(e) => {
+                self.unclaim(i);
+                Err(e)
+            },
         }
-        .ok_or(PioError::TooLarge)?;
-        Ok(Handle {
-            src:    p.wrap_src,
-            mask:   m,
-            offset: s,
-            target: p.wrap_target,
-        })
+    }
+    #[inline]
+    pub fn install<const N: usize>(&self, p: &Program<N>) -> Result<Handle, PioError> {
+        install_program(self.dev, p)
     }
 
     #[inline]
-    pub unsafe fn uninstall(&mut self, h: Handle) {
-        self.used &= !h.mask
+    pub unsafe fn uninstall(&self, h: Handle) {
+        uninstall_program(self.dev, h.mask)
+    }
+    #[inline]
+    pub unsafe fn uninstall_clear(&self, h: Handle) {
+        // NOTE(sf): Zeroed instructions decode as 'jmp 0', so anything still
+        //           wrapping through here won't run stale code.
+        let d = self.ptr();
+        for i in 0..MAX_INSTRUCTIONS {
+            if h.mask & unsafe { 1u32.unchecked_shl(i as u32) } != 0 {
+                d.instr_mem(i).write(|r| unsafe { r.instr_mem0().bits(0) });
+            }
+        }
+        uninstall_program(self.dev, h.mask)
     }
     #[inline]
     pub unsafe fn state_unsafe<'a>(&'a self, i: Slot) -> State<'a, Uninit> {
@@ -223,29 +273,61 @@ impl Pio {
         unsafe { &*self.dev }
     }
     #[inline]
-    fn try_install(&mut self, code: &[u16]) -> Option<(u8, u32)> {
-        for i in 0..MAX_INSTRUCTIONS {
-            match self.try_install_at(i as u8, code) {
-                Some(v) => return Some((i as u8, v)),
-                None => continue,
+    fn claim(&self, i: Slot) -> bool {
+        unsafe {
+            if *self.sm.get() & 1u8.unchecked_shl(i as u32) != 0 {
+                return false;
             }
-        }
-        None
-    }
-    fn try_install_at(&mut self, start: u8, code: &[u16]) -> Option<u32> {
-        let (d, mut u) = (self.ptr(), 0u32);
-        for (i, x) in code.iter().enumerate() {
-            let v = (i as u8 + start).min(31);
-            let m = unsafe { 1u32.unchecked_shl(v as u32) };
-            if (self.used | u) & m != 0 {
-                return None;
+            if !PioAllocator::claim_slot(self.id(), i) {
+                return false;
             }
-            let e = transform(start, *x)?;
-            d.instr_mem(v as usize).write(|r| unsafe { r.instr_mem0().bits(e) });
-            u |= m;
+            *self.sm.get() |= 1u8.unchecked_shl(i as u32);
         }
-        self.used |= u;
-        Some(u)
+        true
+    }
+    #[inline]
+    fn unclaim(&self, i: Slot) {
+        unsafe { *self.sm.get() &= !1u8.unchecked_shl(i as u32) }
+        PioAllocator::release(self.id(), i)
+    }
+}
+impl PioAllocator {
+    pub fn claim() -> Option<(PioID, Slot)> {
+        with(|x| {
+            let u = STATES.borrow_mut(x);
+            for i in 0..8u8 {
+                let m = unsafe { 1u8.unchecked_shl(i as u32) };
+                if *u & m != 0 {
+                    continue;
+                }
+                *u |= m;
+                return Some((if i < 4 { PioID::Pio0 } else { PioID::Pio1 }, Slot::from(i)));
+            }
+            None
+        })
+    }
+    #[inline]
+    pub fn release(i: PioID, s: Slot) {
+        with(|x| *STATES.borrow_mut(x) &= !PioAllocator::mask(i, s))
+    }
+    #[inline]
+    pub fn is_claimed(i: PioID, s: Slot) -> bool {
+        with(|x| *STATES.borrow(x) & PioAllocator::mask(i, s) != 0)
+    }
+    pub fn claim_slot(i: PioID, s: Slot) -> bool {
+        with(|x| {
+            let (u, m) = (STATES.borrow_mut(x), PioAllocator::mask(i, s));
+            if *u & m != 0 {
+                return false;
+            }
+            *u |= m;
+            true
+        })
+    }
+
+    #[inline]
+    fn mask(i: PioID, s: Slot) -> u8 {
+        unsafe { 1u8.unchecked_shl(s as u32 + if matches!(i, PioID::Pio1) { 4 } else { 0 }) }
     }
 }
 impl Handle {
@@ -363,6 +445,22 @@ impl<S: PioState> Machine<S> {
         }
     }
     #[inline]
+    pub fn fifo_debug(&self) -> FifoDebug {
+        let (v, m) = (self.pio().fdebug().read().bits(), unsafe { 1u32.unchecked_shl(self.idx as u32) });
+        FifoDebug {
+            tx_over:  v & unsafe { m.unchecked_shl(16) } != 0,
+            tx_stall: v & unsafe { m.unchecked_shl(24) } != 0,
+            rx_under: v & unsafe { m.unchecked_shl(8) } != 0,
+            rx_stall: v & m != 0,
+        }
+    }
+    #[inline]
+    pub fn clear_fifo_debug(&mut self) {
+        // Each flag is write-one-to-clear.
+        let m = unsafe { 0x01010101u32.unchecked_shl(self.idx as u32) };
+        self.pio().fdebug().write(|r| unsafe { r.bits(m) })
+    }
+    #[inline]
     pub fn drain_fifo(&mut self) {
         let s = self.sm();
         let v = s.sm_shiftctrl().read().fjoin_rx().bit();
@@ -414,6 +512,33 @@ impl<S: PioState> Machine<S> {
     pub unsafe fn exec(&mut self, inst: u16) {
         self.sm().sm_instr().write(|r| unsafe { r.sm0_instr().bits(inst) })
     }
+    pub unsafe fn exec_blocking(&mut self, inst: u16) -> bool {
+        // NOTE(sf): The injected instruction only starts on the next SM clock,
+        //           so EXEC_STALLED isn't meaningful until then. Wait out one
+        //           full divided clock (a zero INT means 65536) before polling.
+        let d = match self.sm().sm_clkdiv().read().int().bits() {
+            0 => 0x10000u32,
+            v => v as u32 + 1,
+        };
+        unsafe { self.exec(inst) };
+        for _ in 0..d {
+            nop();
+        }
+        // NOTE(sf): EXEC_STALLED stays set until the injected instruction
+        //           retires. Give up instead of hanging on a 'wait' or 'pull'
+        //           that will never complete.
+        //
+        //           This only tells us the instruction was accepted, not that
+        //           any delay cycles on it have finished, and if the SM is
+        //           enabled the program may already have moved on afterwards.
+        for _ in 0..EXEC_ATTEMPTS {
+            if !self.is_stalled() {
+                return true;
+            }
+            nop();
+        }
+        false
+    }
 
     #[inline]
     fn sm(&self) -> &SM {
@@ -440,6 +565,29 @@ impl<const N: usize> Program<N> {
         }
     }
 }
+impl Program<MAX_INSTRUCTIONS> {
+    pub fn from_slice(code: &[u16], start: Option<u8>, wrap_src: u8, wrap_target: u8) -> Result<Program, PioError> {
+        let n = code.len();
+        if n == 0 {
+            return Err(PioError::InvalidProgram);
+        }
+        if n > MAX_INSTRUCTIONS || start.is_some_and(|v| v as usize + n > MAX_INSTRUCTIONS) {
+            return Err(PioError::TooLarge);
+        }
+        if wrap_src as usize >= n || wrap_target as usize >= n {
+            return Err(PioError::InvalidProgram);
+        }
+        let mut c = [0u16; MAX_INSTRUCTIONS];
+        unsafe { c.get_unchecked_mut(0..n) }.copy_from_slice(code);
+        Ok(Program {
+            start,
+            wrap_src,
+            wrap_target,
+            len: n as u8,
+            code: c,
+        })
+    }
+}
 impl<'a, S: PioState> State<'a, S> {
     #[inline]
     pub fn group(self, other: State<'a, S>) -> StateGroup2<'a, S> {
@@ -554,6 +702,40 @@ impl<S: PioStateOccupied> Machine<S> {
             }
         });
     }
+    pub fn self_test(&mut self) -> bool {
+        let mut r = false;
+        // NOTE(sf): This injects the echo instead of running an installed
+        //           program, so the SM's instruction memory is left alone but
+        //           the OSR/ISR and FIFOs are clobbered.
+        self.paused(|m| {
+            m.drain_fifo();
+            let (mut t, mut x) = (m.tx_u32(), m.rx_u32());
+            for v in [0xA5A55A5Au32, 0x0FF0F00Fu32] {
+                if t.try_write_raw(v).is_err() {
+                    return;
+                }
+                // pull block
+                // mov isr, osr
+                // push block
+                if unsafe { !m.exec_blocking(0x80A0) || !m.exec_blocking(0xA0C7) || !m.exec_blocking(0x8020) } {
+                    return;
+                }
+                let mut o = None;
+                for _ in 0..EXEC_ATTEMPTS {
+                    o = x.try_read_raw();
+                    if o.is_some() {
+                        break;
+                    }
+                    nop();
+                }
+                if o != Some(v) {
+                    return;
+                }
+            }
+            r = true;
+        });
+        r
+    }
 
     fn paused(&mut self, func: impl FnOnce(&mut Machine<S>)) {
         let x = self.is_enabled();
@@ -609,6 +791,26 @@ impl Clone for Slot {
     }
 }
 
+impl From<u8> for Slot {
+    #[inline]
+    fn from(v: u8) -> Slot {
+        match v & 0x3 {
+            0 => Slot::Index0,
+            1 => Slot::Index1,
+            2 => Slot::Index2,
+            _ => Slot::Index3,
+        }
+    }
+}
+
+impl Copy for FifoDebug {}
+impl Clone for FifoDebug {
+    #[inline]
+    fn clone(&self) -> FifoDebug {
+        *self
+    }
+}
+
 impl Copy for PioID {}
 impl Clone for PioID {
     #[inline]
@@ -637,9 +839,11 @@ impl Debug for PioError {
     #[inline]
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
+            PioError::InUse => f.write_str("InUse"),
             PioError::TooLarge => f.write_str("TooLarge"),
             PioError::WouldBlock => f.write_str("WouldBlock"),
             PioError::InvalidProgram => f.write_str("InvalidProgram"),
+            PioError::InvalidPins => f.write_str("InvalidPins"),
         }
     }
     #[cfg(not(feature = "debug"))]
@@ -663,17 +867,160 @@ unsafe impl<S: PioState> Send for Machine<S> {}
 unsafe impl<'a, S: PioState> Send for State<'a, S> {}
 
 #[inline]
-fn transform(start: u8, x: u16) -> Option<u16> {
+fn index(dev: *const RegisterBlock) -> usize {
+    if dev == PIO0::PTR { 0usize } else { 1usize }
+}
+fn install_program<const N: usize>(dev: *const RegisterBlock, p: &Program<N>) -> Result<Handle, PioError> {
+    if p.len == 0 {
+        return Err(PioError::InvalidProgram);
+    }
+    let n = (p.len as usize).min(N);
+    if n > MAX_INSTRUCTIONS {
+        return Err(PioError::TooLarge);
+    }
+    if p.wrap_src as usize >= n || p.wrap_target as usize >= n {
+        return Err(PioError::InvalidProgram);
+    }
+    let c = unsafe { p.code.get_unchecked(0..n) };
+    let (s, m) = match p.start {
+        Some(v) => try_install_at(dev, v, c).map(|r| (v, r)),
+        None => try_install(dev, c),
+    }
+    .ok_or(PioError::TooLarge)?;
+    Ok(Handle {
+        src:    p.wrap_src,
+        mask:   m,
+        offset: s,
+        target: p.wrap_target,
+    })
+}
+#[inline]
+fn uninstall_program(dev: *const RegisterBlock, mask: u32) {
+    with(|x| PROGRAMS.borrow_mut(x)[index(dev)] &= !mask)
+}
+#[inline]
+fn try_install(dev: *const RegisterBlock, code: &[u16]) -> Option<(u8, u32)> {
+    for i in 0..MAX_INSTRUCTIONS {
+        match try_install_at(dev, i as u8, code) {
+            Some(v) => return Some((i as u8, v)),
+            None => continue,
+        }
+    }
+    None
+}
+fn try_install_at(dev: *const RegisterBlock, start: u8, code: &[u16]) -> Option<u32> {
+    if start as usize + code.len() > MAX_INSTRUCTIONS {
+        return None;
+    }
+    // NOTE(sf): Check every instruction before writing any, so a failed
+    //           install doesn't leave half a program in free memory.
+    for x in code.iter() {
+        transform(start, code.len() as u8, *x)?;
+    }
+    let u = program_mask(start, code.len());
+    // Reserve the space first, so nothing else can take it while we write.
+    let r = with(|x| {
+        let v = &mut PROGRAMS.borrow_mut(x)[index(dev)];
+        if *v & u != 0 {
+            return false;
+        }
+        *v |= u;
+        true
+    });
+    if !r {
+        return None;
+    }
+    let d = unsafe { &*dev };
+    for (i, x) in code.iter().enumerate() {
+        let e = unsafe { transform(start, code.len() as u8, *x).unwrap_unchecked() };
+        d.instr_mem(i + start as usize).write(|r| unsafe { r.instr_mem0().bits(e) });
+    }
+    Some(u)
+}
+#[inline]
+const fn program_is(p: &Program, c: &[u16]) -> bool {
+    // NOTE(sf): Lets the drivers check their 'pio_asm!' output at compile
+    //           time. Anything past the end of 'c' must be empty.
+    let mut i = 0usize;
+    while i < p.code.len() {
+        if p.code[i] != if i < c.len() { c[i] } else { 0u16 } {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+#[inline]
+const fn program_mask(start: u8, len: usize) -> u32 {
+    match u32::MAX.checked_shr(32 - len as u32) {
+        Some(v) => v.wrapping_shl(start as u32),
+        None => 0u32,
+    }
+}
+
+const _: () = {
+    // NOTE(sf): Instruction memory is only reachable on target, so this
+    //           checks the slots 'uninstall_clear' zeroes instead. B reuses
+    //           A's offset and A's tail is what B would otherwise see.
+    let (a, b) = (program_mask(4, 6), program_mask(4, 3));
+    assert!(a == 0x3F0 && b == 0x70);
+    let mut u = a;
+    // B doesn't fit until A is uninstalled.
+    assert!(u & b != 0);
+    u &= !a;
+    assert!(u & b == 0);
+    // A's tail, left zeroed by 'uninstall_clear'.
+    assert!(a & !b == 0x380);
+    assert!(program_mask(0, 32) == u32::MAX);
+    assert!(program_mask(0, 0) == 0);
+};
+#[inline]
+const fn transform(start: u8, len: u8, x: u16) -> Option<u16> {
+    // NOTE(sf): Only JMP (opcode 000) carries an address, the low 5 bits of
+    //           every other instruction are bit counts, indexes or data and
+    //           must not be relocated.
     if x & 0xE000 != 0 {
         return Some(x);
     }
-    let v = (x & 0x1F) as u8 + start;
-    if v > MAX_INSTRUCTIONS as u8 {
+    let a = (x & 0x1F) as u8;
+    // Jumping outside of our own program would run someone else's code.
+    if a >= len {
+        return None;
+    }
+    let v = a + start;
+    if v as usize >= MAX_INSTRUCTIONS {
         return None;
     }
     Some((x & 0xFFE0) | v as u16)
 }
 
+const _: () = {
+    // set x, 7 / loop: out pins, 1 / jmp x-- 1 / in pins, 31 / jmp !osre 0 / mov x, y
+    let c = [0xE027u16, 0x6001, 0x0041, 0x401F, 0x00E0, 0xA022];
+    let mut s = 0u8;
+    while s <= 10 {
+        let mut i = 0usize;
+        while i < c.len() {
+            let v = match transform(s, c.len() as u8, c[i]) {
+                Some(v) => v,
+                None => panic!("pio: relocation failed"),
+            };
+            if c[i] & 0xE000 == 0 {
+                // The condition bits stay, the target moves with the program.
+                assert!(v & 0xFFE0 == c[i] & 0xFFE0);
+                assert!(v & 0x1F == (c[i] & 0x1F) + s as u16);
+            } else {
+                assert!(v == c[i]);
+            }
+            i += 1;
+        }
+        s += 10;
+    }
+    // Jumps out of the program or past the end of memory are rejected.
+    assert!(transform(0, 6, 0x0006).is_none());
+    assert!(transform(28, 6, 0x0005).is_none());
+};
+
 pub mod state {
     pub struct Uninit;
     pub struct Running;
@@ -0,0 +1,697 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::assert;
+use core::clone::Clone;
+use core::default::Default;
+use core::marker::Copy;
+use core::option::Option::{self, None, Some};
+use core::panic;
+
+use crate::pio::{MAX_INSTRUCTIONS, Program};
+
+#[repr(u8)]
+pub enum IrqMode {
+    Set   = 0x00u8,
+    Wait  = 0x20u8,
+    Clear = 0x40u8,
+}
+#[repr(u8)]
+pub enum InSource {
+    Pins = 0u8,
+    X    = 1u8,
+    Y    = 2u8,
+    Null = 3u8,
+    Isr  = 6u8,
+    Osr  = 7u8,
+}
+#[repr(u8)]
+pub enum MovSource {
+    Pins   = 0u8,
+    X      = 1u8,
+    Y      = 2u8,
+    Null   = 3u8,
+    Status = 5u8,
+    Isr    = 6u8,
+    Osr    = 7u8,
+}
+#[repr(u8)]
+pub enum WaitSource {
+    Gpio        = 0x00u8,
+    Pin         = 0x01u8,
+    Irq         = 0x02u8,
+    IrqRelative = 0x12u8, // Irq with the relative index bit set.
+}
+#[repr(u8)]
+pub enum MovOperation {
+    None    = 0u8,
+    Invert  = 1u8,
+    Reverse = 2u8,
+}
+#[repr(u8)]
+pub enum JmpCondition {
+    Always      = 0u8,
+    NotX        = 1u8,
+    XDecrement  = 2u8,
+    NotY        = 3u8,
+    YDecrement  = 4u8,
+    NotEqual    = 5u8,
+    Pin         = 6u8,
+    NotOsrEmpty = 7u8,
+}
+#[repr(u8)]
+pub enum SetDestination {
+    Pins    = 0u8,
+    X       = 1u8,
+    Y       = 2u8,
+    PinDirs = 4u8,
+}
+#[repr(u8)]
+pub enum OutDestination {
+    Pins    = 0u8,
+    X       = 1u8,
+    Y       = 2u8,
+    Null    = 3u8,
+    PinDirs = 4u8,
+    Pc      = 5u8,
+    Isr     = 6u8,
+    Exec    = 7u8,
+}
+#[repr(u8)]
+pub enum MovDestination {
+    Pins = 0u8,
+    X    = 1u8,
+    Y    = 2u8,
+    Exec = 4u8,
+    Pc   = 5u8,
+    Isr  = 6u8,
+    Osr  = 7u8,
+}
+
+pub struct Label(u8);
+pub struct Assembler<const N: usize = MAX_INSTRUCTIONS> {
+    code:   [u16; N],
+    fix:    u32,
+    len:    u8,
+    opt:    bool,
+    side:   u8,
+    wrap:   Option<u8>,
+    start:  Option<u8>,
+    count:  u8,
+    target: u8,
+    labels: [u8; MAX_INSTRUCTIONS],
+}
+
+impl<const N: usize> Assembler<N> {
+    #[inline]
+    pub const fn new() -> Assembler<N> {
+        Assembler {
+            code:   [0u16; N],
+            fix:    0u32,
+            len:    0u8,
+            opt:    false,
+            side:   0u8,
+            wrap:   None,
+            start:  None,
+            count:  0u8,
+            target: 0u8,
+            labels: [0xFFu8; MAX_INSTRUCTIONS],
+        }
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    #[inline]
+    pub const fn label(&mut self) -> Label {
+        if self.count as usize >= MAX_INSTRUCTIONS {
+            panic!("pio: too many labels");
+        }
+        self.count += 1;
+        Label(self.count - 1)
+    }
+    #[inline]
+    pub const fn bind(&mut self, l: Label) -> &mut Assembler<N> {
+        if self.labels[l.0 as usize] != 0xFF {
+            panic!("pio: label bound twice");
+        }
+        self.labels[l.0 as usize] = self.len;
+        self
+    }
+    #[inline]
+    pub const fn wrap(&mut self) -> &mut Assembler<N> {
+        self.wrap = Some(self.last() as u8);
+        self
+    }
+    #[inline]
+    pub const fn origin(&mut self, addr: u8) -> &mut Assembler<N> {
+        if addr as usize >= MAX_INSTRUCTIONS {
+            panic!("pio: origin out of range");
+        }
+        self.start = Some(addr);
+        self
+    }
+    #[inline]
+    pub const fn side_set(&mut self, bits: u8, opt: bool) -> &mut Assembler<N> {
+        if self.len > 0 {
+            panic!("pio: side_set must come before any instructions");
+        }
+        let n = if opt { bits + 1 } else { bits };
+        if n > 5 {
+            panic!("pio: side_set uses more than 5 bits");
+        }
+        (self.side, self.opt) = (n, opt);
+        self
+    }
+    #[inline]
+    pub const fn wrap_target(&mut self) -> &mut Assembler<N> {
+        self.target = self.len;
+        self
+    }
+
+    #[inline]
+    pub const fn side(&mut self, v: u8) -> &mut Assembler<N> {
+        let (i, b) = (self.last(), if self.opt { self.side - 1 } else { self.side });
+        if b == 0 || v as u32 >= 1u32 << b {
+            panic!("pio: side-set value out of range");
+        }
+        // Side-Set bits live at the top of the delay/side-set field, with
+        // the enable flag (if optional) at bit 12.
+        self.code[i] |= (v as u16) << (13 - self.side);
+        if self.opt {
+            self.code[i] |= 0x1000;
+        }
+        self
+    }
+    #[inline]
+    pub const fn delay(&mut self, d: u8) -> &mut Assembler<N> {
+        let i = self.last();
+        if d as u32 >= 1u32 << (5 - self.side) {
+            panic!("pio: delay out of range");
+        }
+        self.code[i] |= (d as u16) << 8;
+        self
+    }
+
+    #[inline]
+    pub const fn nop(&mut self) -> &mut Assembler<N> {
+        // mov y, y
+        self.emit(0xA042)
+    }
+    #[inline]
+    pub const fn jmp(&mut self, c: JmpCondition, l: Label) -> &mut Assembler<N> {
+        // NOTE(sf): The label index is stored in the address field until
+        //           'assemble' patches it with the bound address.
+        self.fix |= 1u32 << self.len;
+        self.emit((c as u16) << 5 | l.0 as u16)
+    }
+    #[inline]
+    pub const fn push(&mut self, if_full: bool, block: bool) -> &mut Assembler<N> {
+        self.emit(0x8000 | (if_full as u16) << 6 | (block as u16) << 5)
+    }
+    #[inline]
+    pub const fn pull(&mut self, if_empty: bool, block: bool) -> &mut Assembler<N> {
+        self.emit(0x8080 | (if_empty as u16) << 6 | (block as u16) << 5)
+    }
+    #[inline]
+    pub const fn r#in(&mut self, s: InSource, bits: u8) -> &mut Assembler<N> {
+        self.emit(0x4000 | (s as u16) << 5 | bit_count(bits))
+    }
+    #[inline]
+    pub const fn out(&mut self, d: OutDestination, bits: u8) -> &mut Assembler<N> {
+        self.emit(0x6000 | (d as u16) << 5 | bit_count(bits))
+    }
+    #[inline]
+    pub const fn set(&mut self, d: SetDestination, v: u8) -> &mut Assembler<N> {
+        if v > 0x1F {
+            panic!("pio: set value out of range");
+        }
+        self.emit(0xE000 | (d as u16) << 5 | v as u16)
+    }
+    #[inline]
+    pub const fn irq(&mut self, m: IrqMode, idx: u8, rel: bool) -> &mut Assembler<N> {
+        if idx > 7 {
+            panic!("pio: irq index out of range");
+        }
+        self.emit(0xC000 | m as u16 | (rel as u16) << 4 | idx as u16)
+    }
+    #[inline]
+    pub const fn wait(&mut self, polarity: bool, s: WaitSource, idx: u8) -> &mut Assembler<N> {
+        let v = s as u8;
+        if idx > 0x1F || (v & 0x2 != 0 && idx > 7) {
+            panic!("pio: wait index out of range");
+        }
+        self.emit(0x2000 | (polarity as u16) << 7 | ((v & 0x3) as u16) << 5 | (v & 0x10 | idx) as u16)
+    }
+    #[inline]
+    pub const fn mov(&mut self, d: MovDestination, op: MovOperation, s: MovSource) -> &mut Assembler<N> {
+        self.emit(0xA000 | (d as u16) << 5 | (op as u16) << 3 | s as u16)
+    }
+
+    pub const fn assemble(&self) -> Program<N> {
+        if self.len == 0 {
+            panic!("pio: empty program");
+        }
+        let (mut c, mut i) = (self.code, 0usize);
+        while i < self.len as usize {
+            if self.fix & (1u32 << i) != 0 {
+                let v = self.labels[(c[i] & 0x1F) as usize];
+                if v == 0xFF {
+                    panic!("pio: jump to an unbound label");
+                }
+                c[i] = (c[i] & 0xFFE0) | v as u16;
+            }
+            i += 1;
+        }
+        Program {
+            code:        c,
+            len:         self.len,
+            start:       self.start,
+            wrap_src:    match self.wrap {
+                Some(v) => v,
+                None => self.len - 1,
+            },
+            wrap_target: self.target,
+        }
+    }
+
+    #[inline]
+    const fn last(&self) -> usize {
+        if self.len == 0 {
+            panic!("pio: no instruction to modify");
+        }
+        self.len as usize - 1
+    }
+    #[inline]
+    const fn emit(&mut self, v: u16) -> &mut Assembler<N> {
+        if self.len as usize >= N || self.len as usize >= MAX_INSTRUCTIONS {
+            panic!("pio: program too large");
+        }
+        self.code[self.len as usize] = v;
+        self.len += 1;
+        self
+    }
+}
+
+impl Copy for Label {}
+impl Clone for Label {
+    #[inline]
+    fn clone(&self) -> Label {
+        *self
+    }
+}
+
+impl Copy for IrqMode {}
+impl Clone for IrqMode {
+    #[inline]
+    fn clone(&self) -> IrqMode {
+        *self
+    }
+}
+
+impl Copy for InSource {}
+impl Clone for InSource {
+    #[inline]
+    fn clone(&self) -> InSource {
+        *self
+    }
+}
+
+impl Copy for MovSource {}
+impl Clone for MovSource {
+    #[inline]
+    fn clone(&self) -> MovSource {
+        *self
+    }
+}
+
+impl Copy for WaitSource {}
+impl Clone for WaitSource {
+    #[inline]
+    fn clone(&self) -> WaitSource {
+        *self
+    }
+}
+
+impl Copy for MovOperation {}
+impl Clone for MovOperation {
+    #[inline]
+    fn clone(&self) -> MovOperation {
+        *self
+    }
+}
+
+impl Copy for JmpCondition {}
+impl Clone for JmpCondition {
+    #[inline]
+    fn clone(&self) -> JmpCondition {
+        *self
+    }
+}
+
+impl Copy for SetDestination {}
+impl Clone for SetDestination {
+    #[inline]
+    fn clone(&self) -> SetDestination {
+        *self
+    }
+}
+
+impl Copy for OutDestination {}
+impl Clone for OutDestination {
+    #[inline]
+    fn clone(&self) -> OutDestination {
+        *self
+    }
+}
+
+impl Copy for MovDestination {}
+impl Clone for MovDestination {
+    #[inline]
+    fn clone(&self) -> MovDestination {
+        *self
+    }
+}
+
+impl<const N: usize> Default for Assembler<N> {
+    #[inline]
+    fn default() -> Assembler<N> {
+        Assembler::new()
+    }
+}
+
+#[inline]
+pub const fn with_delay(inst: u16, delay: u8) -> u16 {
+    // NOTE(sf): Only valid when no side-set is configured, otherwise use
+    //           'with_side' so the delay gets the right width.
+    if delay > 0x1F {
+        panic!("pio: delay out of range");
+    }
+    (inst & 0xE0FF) | (delay as u16) << 8
+}
+#[inline]
+pub const fn with_side(inst: u16, side: u8, delay: u8, sideset_bits: u8) -> u16 {
+    // NOTE(sf): 'sideset_bits' is the SIDESET_COUNT value, which includes the
+    //           enable bit for optional side-set. In that case, the enable
+    //           flag is the top bit of 'side'.
+    if sideset_bits > 5 {
+        panic!("pio: side_set uses more than 5 bits");
+    }
+    if side as u32 >= 1u32 << sideset_bits {
+        panic!("pio: side-set value out of range");
+    }
+    if delay as u32 >= 1u32 << (5 - sideset_bits) {
+        panic!("pio: delay out of range");
+    }
+    (inst & 0xE0FF) | (side as u16) << (13 - sideset_bits) | (delay as u16) << 8
+}
+
+const _: () = {
+    // nop [4] and out x, 1 [31]
+    assert!(with_delay(0xA042, 4) == 0xA442);
+    assert!(with_delay(0x6021, 31) == 0x7F21);
+    // Existing delay bits are replaced, not merged.
+    assert!(with_delay(0x7F21, 2) == 0x6221);
+    // .side_set 1 (WS2812 and the Cyw gSPI program).
+    assert!(with_side(0x6021, 0, 2, 1) == 0x6221); // out x, 1 side 0 [2]
+    assert!(with_side(0x0023, 1, 1, 1) == 0x1123); // jmp !x 3 side 1 [1]
+    assert!(with_side(0x0000, 1, 4, 1) == 0x1400); // jmp 0 side 1 [4]
+    assert!(with_side(0x0040, 1, 0, 1) == 0x1040); // jmp x-- 0 side 1
+    // .side_set 1 opt, the enable flag is the top bit of 'side' (I2C).
+    assert!(with_side(0xA042, 3, 2, 2) == 0xBA42); // nop side 1 [2]
+    assert!(with_side(0x0043, 2, 7, 2) == 0x1743); // jmp x-- 3 side 0 [7]
+    assert!(with_side(0x00C0, 2, 2, 2) == 0x12C0); // jmp pin 0 side 0 [2]
+    // All five bits as side-set leaves no room for delay.
+    assert!(with_side(0xE000, 0x1F, 0, 5) == 0xFF00);
+};
+
+#[inline]
+const fn bit_count(v: u8) -> u16 {
+    // A bit count of 32 is encoded as zero.
+    match v {
+        1..=31 => v as u16,
+        32 => 0u16,
+        _ => panic!("pio: bit count out of range"),
+    }
+}
+
+// Assembles PIO mnemonics into a 'Program' at compile time (when used in a
+// const) using 'Assembler'. Statements are ';' terminated and labels are plain
+// identifiers followed by ':'.
+//
+//  const WS2812: Program = pio_asm!(
+//      .side_set 1;
+//      .wrap_target;
+//      bitloop:
+//          out x, 1        side 0 [2];
+//          jmp !x do_zero  side 1 [1];
+//          jmp bitloop     side 1 [4];
+//      do_zero:
+//          nop             side 0 [4];
+//      .wrap;
+//  );
+#[macro_export]
+macro_rules! pio_asm {
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*]) => {{
+        let mut $a = $crate::pio::Assembler::<{ $crate::pio::MAX_INSTRUCTIONS }>::new();
+        $(let $l = $a.label();)*
+        $($c)*
+        $a.assemble()
+    }};
+    // Directives
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] . side_set $n:literal opt ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.side_set($n, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] . side_set $n:literal ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.side_set($n, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] . origin $n:literal ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.origin($n);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] . wrap_target ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.wrap_target();] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] . wrap ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.wrap();] $($r)*)
+    };
+    // Labels
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] $n:ident : $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)* $n] [$($c)* $a.bind($n);] $($r)*)
+    };
+    // JMP
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp ! x $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::NotX, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp x - - $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::XDecrement, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp ! y $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::NotY, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp y - - $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::YDecrement, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp x != y $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::NotEqual, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp pin $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::Pin, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp ! osre $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::NotOsrEmpty, $t);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] jmp $t:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.jmp($crate::pio::JmpCondition::Always, $t);] $($r)*)
+    };
+    // WAIT
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] wait $p:literal gpio $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.wait($p != 0, $crate::pio::WaitSource::Gpio, $i);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] wait $p:literal pin $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.wait($p != 0, $crate::pio::WaitSource::Pin, $i);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] wait $p:literal irq $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.wait($p != 0, $crate::pio::WaitSource::IrqRelative, $i);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] wait $p:literal irq $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.wait($p != 0, $crate::pio::WaitSource::Irq, $i);] $($r)*)
+    };
+    // IN/OUT
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] in $s:ident , $n:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.r#in($crate::pio_asm!(@in $s), $n);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] out $d:ident , $n:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.out($crate::pio_asm!(@out $d), $n);] $($r)*)
+    };
+    // PUSH/PULL
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push iffull block $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(true, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push iffull noblock $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(true, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push iffull $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(true, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push noblock $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(false, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push block $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(false, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] push $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.push(false, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull ifempty block $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(true, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull ifempty noblock $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(true, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull ifempty $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(true, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull noblock $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(false, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull block $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(false, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] pull $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.pull(false, true);] $($r)*)
+    };
+    // MOV
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] mov $d:ident , ! $s:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.mov($crate::pio_asm!(@movd $d), $crate::pio::MovOperation::Invert, $crate::pio_asm!(@movs $s));] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] mov $d:ident , ~ $s:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.mov($crate::pio_asm!(@movd $d), $crate::pio::MovOperation::Invert, $crate::pio_asm!(@movs $s));] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] mov $d:ident , :: $s:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.mov($crate::pio_asm!(@movd $d), $crate::pio::MovOperation::Reverse, $crate::pio_asm!(@movs $s));] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] mov $d:ident , $s:ident $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.mov($crate::pio_asm!(@movd $d), $crate::pio::MovOperation::None, $crate::pio_asm!(@movs $s));] $($r)*)
+    };
+    // IRQ
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq set $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq set $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq nowait $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq nowait $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq wait $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Wait, $i, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq wait $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Wait, $i, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq clear $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Clear, $i, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq clear $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Clear, $i, false);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq $i:literal rel $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, true);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] irq $i:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.irq($crate::pio::IrqMode::Set, $i, false);] $($r)*)
+    };
+    // SET/NOP
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] set $d:ident , $v:literal $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.set($crate::pio_asm!(@set $d), $v);] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] nop $($r:tt)*) => {
+        $crate::pio_asm!(@suf $a [$($l)*] [$($c)* $a.nop();] $($r)*)
+    };
+    (@stmt $a:ident [$($l:ident)*] [$($c:tt)*] $($r:tt)+) => {
+        ::core::compile_error!("pio_asm: invalid or unsupported instruction")
+    };
+    // Side-Set and Delay
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)*] $($r)*)
+    };
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] side $v:literal [$d:literal] ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.side($v); $a.delay($d);] $($r)*)
+    };
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] [$d:literal] side $v:literal ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.side($v); $a.delay($d);] $($r)*)
+    };
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] side $v:literal ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.side($v);] $($r)*)
+    };
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] [$d:literal] ; $($r:tt)*) => {
+        $crate::pio_asm!(@stmt $a [$($l)*] [$($c)* $a.delay($d);] $($r)*)
+    };
+    (@suf $a:ident [$($l:ident)*] [$($c:tt)*] $($r:tt)*) => {
+        ::core::compile_error!("pio_asm: invalid side-set/delay or missing ';'")
+    };
+    // Operand names
+    (@in pins) => { $crate::pio::InSource::Pins };
+    (@in x) => { $crate::pio::InSource::X };
+    (@in y) => { $crate::pio::InSource::Y };
+    (@in null) => { $crate::pio::InSource::Null };
+    (@in isr) => { $crate::pio::InSource::Isr };
+    (@in osr) => { $crate::pio::InSource::Osr };
+    (@out pins) => { $crate::pio::OutDestination::Pins };
+    (@out x) => { $crate::pio::OutDestination::X };
+    (@out y) => { $crate::pio::OutDestination::Y };
+    (@out null) => { $crate::pio::OutDestination::Null };
+    (@out pindirs) => { $crate::pio::OutDestination::PinDirs };
+    (@out pc) => { $crate::pio::OutDestination::Pc };
+    (@out isr) => { $crate::pio::OutDestination::Isr };
+    (@out exec) => { $crate::pio::OutDestination::Exec };
+    (@movd pins) => { $crate::pio::MovDestination::Pins };
+    (@movd x) => { $crate::pio::MovDestination::X };
+    (@movd y) => { $crate::pio::MovDestination::Y };
+    (@movd exec) => { $crate::pio::MovDestination::Exec };
+    (@movd pc) => { $crate::pio::MovDestination::Pc };
+    (@movd isr) => { $crate::pio::MovDestination::Isr };
+    (@movd osr) => { $crate::pio::MovDestination::Osr };
+    (@movs pins) => { $crate::pio::MovSource::Pins };
+    (@movs x) => { $crate::pio::MovSource::X };
+    (@movs y) => { $crate::pio::MovSource::Y };
+    (@movs null) => { $crate::pio::MovSource::Null };
+    (@movs status) => { $crate::pio::MovSource::Status };
+    (@movs isr) => { $crate::pio::MovSource::Isr };
+    (@movs osr) => { $crate::pio::MovSource::Osr };
+    (@set pins) => { $crate::pio::SetDestination::Pins };
+    (@set x) => { $crate::pio::SetDestination::X };
+    (@set y) => { $crate::pio::SetDestination::Y };
+    (@set pindirs) => { $crate::pio::SetDestination::PinDirs };
+    ($($t:tt)+) => {
+        $crate::pio_asm!(@stmt asm [] [] $($t)+)
+    };
+}
@@ -26,10 +26,11 @@ use core::default::Default;
 use core::marker::Copy;
 use core::matches;
 use core::option::Option::{None, Some};
+use core::result::Result::{self, Err, Ok};
 
 use crate::pin::PinID;
 use crate::pio::state::{Stopped, Uninit};
-use crate::pio::{Handle, State};
+use crate::pio::{Handle, PioError, State};
 
 pub enum Fifo {
     Tx,
@@ -353,6 +354,25 @@ impl Config {
         self.wrap_bottom = h.wrap_target_adjusted();
     }
 
+    #[inline]
+    pub const fn validate(&self) -> Result<(), PioError> {
+        // NOTE(sf): Joining a FIFO removes the other direction, so autopush
+        //           with a TX join (or autopull with a RX join) would stall
+        //           the SM forever.
+        match self.fifo {
+            Fifo::Tx if self.push_auto => Err(PioError::InvalidProgram),
+            Fifo::Rx if self.pull_auto => Err(PioError::InvalidProgram),
+            _ => Ok(()),
+        }
+    }
+    /// Same as 'configure', but checks the Config with 'validate' first. The
+    /// State is consumed on error, so use 'validate' beforehand if the slot
+    /// needs to be released.
+    #[inline]
+    pub fn try_configure<'a>(&self, s: State<'a, Uninit>) -> Result<State<'a, Stopped>, PioError> {
+        self.validate()?;
+        Ok(self.configure(s))
+    }
     pub fn configure<'a>(&self, mut s: State<'a, Uninit>) -> State<'a, Stopped> {
         s.set_state(false);
         let v = s.m.sm();
@@ -0,0 +1,174 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::assert;
+use core::matches;
+use core::mem::ManuallyDrop;
+use core::ops::{Drop, FnOnce};
+use core::option::Option::Some;
+use core::ptr::read;
+use core::result::Result::{self, Err, Ok};
+
+use crate::pin::{PinDirection, PinID, PinPull};
+use crate::pio::state::{Running, Stopped, Uninit};
+use crate::pio::{Config, Handle, PioError, Program, Shift, State, install_program, program_is, uninstall_program};
+use crate::pio_asm;
+
+// mov isr, null / in pins, 2 / mov y, isr
+const INIT: [u16; 3] = [0xA0C3u16, 0x4002u16, 0xA046u16];
+// mov x, null
+const CLEAR: u16 = 0xA023u16;
+
+// Y holds the last and current Pin states as a 4bit index into the jump table
+// at the start of the program (which must be at address 0), X holds the
+// position.
+const PROGRAM: Program = pio_asm!(
+    .origin 0;
+        jmp update;                 // 00 -> 00
+        jmp decrement;              // 00 -> 01
+        jmp increment;              // 00 -> 10
+        jmp update;                 // 00 -> 11
+        jmp increment;              // 01 -> 00
+        jmp update;                 // 01 -> 01
+        jmp update;                 // 01 -> 10
+        jmp decrement;              // 01 -> 11
+        jmp decrement;              // 10 -> 00
+        jmp update;                 // 10 -> 01
+        jmp update;                 // 10 -> 10
+        jmp increment;              // 10 -> 11
+        jmp update;                 // 11 -> 00
+        jmp increment;              // 11 -> 01
+    decrement:
+        jmp x-- update;             // 11 -> 10
+    .wrap_target;
+    update:                         // 11 -> 11
+        mov isr, null;
+        in y, 2;
+        in pins, 2;
+        mov y, isr;
+        mov pc, isr;
+    increment:
+        mov x, ~x;
+        jmp x-- increment_cont;
+    increment_cont:
+        mov x, ~x;
+    .wrap;
+);
+// 'update' is 15, 'decrement' is 14 and 'increment' is 20.
+const _: () = {
+    assert!(program_is(&PROGRAM, &[
+        0x000Fu16, 0x000E, 0x0014, 0x000F, // 00 - 03: jump table
+        0x0014, 0x000F, 0x000F, 0x000E, // 04 - 07
+        0x000E, 0x000F, 0x000F, 0x0014, // 08 - 11
+        0x000F, 0x0014, // 12 - 13
+        0x004F, // 14: jmp x--, 15
+        0xA0C3, // 15: mov isr, null
+        0x4042, // 16: in y, 2
+        0x4002, // 17: in pins, 2
+        0xA046, // 18: mov y, isr
+        0xA0A6, // 19: mov pc, isr
+        0xA029, // 20: mov x, ~x
+        0x0056, // 21: jmp x--, 22
+        0xA029, // 22: mov x, ~x
+    ]));
+    assert!(matches!(PROGRAM.start, Some(0)));
+    assert!(PROGRAM.wrap_src == 22 && PROGRAM.wrap_target == 15);
+};
+
+pub struct Encoder<'a> {
+    h:     Handle,
+    sm:    State<'a, Running>,
+    entry: u8,
+}
+
+impl<'a> Encoder<'a> {
+    // NOTE(sf): The encoder inputs must be consecutive Pins, as the program
+    //           reads both at once. The program must also be installed at
+    //           address 0 as it jumps directly into the state table.
+    //
+    //           The program goes into the same PIO block as 's' and is
+    //           uninstalled when this is dropped. Use 'stop' to keep it.
+    pub fn new(s: State<'a, Uninit>, pins: &[PinID]) -> Result<Encoder<'a>, PioError> {
+        match pins {
+            [a, b] if *b as u8 == *a as u8 + 1 => (),
+            _ => return Err(PioError::InvalidPins),
+        }
+        let h = install_program(s.pio, &PROGRAM)?;
+        let mut s = Config::new_with(&h)
+            .input_pins(pins)
+            .push(true, 32, Shift::Left)
+            .configure(s);
+        s.set_pins_direction(PinDirection::In, pins);
+        for i in pins.iter() {
+            i.set_pull(PinPull::Up);
+        }
+        // Load the current Pin state so the first sample doesn't count.
+        for i in INIT.iter() {
+            unsafe { s.exec(*i) };
+        }
+        let e = h.wrap_target_adjusted();
+        unsafe { s.jump(e) };
+        Ok(Encoder { sm: s.start(), entry: e, h })
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        self.paused(|e| unsafe { e.sm.exec(CLEAR) })
+    }
+    #[inline]
+    pub fn stop(self) -> (State<'a, Stopped>, Handle) {
+        let v = ManuallyDrop::new(self);
+        unsafe { (read(&v.sm).stop(), read(&v.h)) }
+    }
+    #[inline]
+    pub fn position(&mut self) -> i32 {
+        let mut v = 0i32;
+        self.paused(|e| v = e.sm.x() as i32);
+        v
+    }
+
+    fn paused(&mut self, func: impl FnOnce(&mut Encoder<'a>)) {
+        // NOTE(sf): 'Machine::x' goes through the ISR, which the program uses
+        //           to build the jump index, and X is mid-update when
+        //           incrementing. Only touch them when stopped at the top of
+        //           the loop.
+        loop {
+            self.sm.set_state(false);
+            if self.sm.pc() as u8 == self.entry {
+                break;
+            }
+            self.sm.set_state(true);
+        }
+        func(self);
+        self.sm.set_state(true);
+    }
+}
+
+impl Drop for Encoder<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // Stop the StateMachine before its program goes away.
+        self.sm.set_state(false);
+        uninstall_program(self.sm.pio, self.h.mask)
+    }
+}
@@ -0,0 +1,344 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::assert;
+use core::cmp::Ord;
+use core::mem::ManuallyDrop;
+use core::ops::Drop;
+use core::option::Option::Some;
+use core::ptr::read;
+use core::result::Result::{self, Err, Ok};
+
+use crate::Board;
+use crate::asm::nop;
+use crate::i2c::{I2cAddress, I2cError};
+use crate::pin::{PinDirection, PinID, PinPull, PinState};
+use crate::pio::state::{Running, Stopped, Uninit};
+use crate::pio::{Config, Handle, PioError, Program, Rx, Shift, State, Tx, install_program, program_is, uninstall_program};
+use crate::pio_asm;
+
+// Each SCL period is 32 PIO cycles, so the bus clock is sys_freq / (32 * div)
+// with 'div' between 1 and 65535 (plus 8 bits of fraction). At 125MHz that
+// allows ~60Hz to ~3.9MHz, but anything past 1MHz (Fast-mode Plus) is out of
+// spec for most devices and limited by the pull-up rise time.
+const CYCLES: u64 = 32u64;
+
+// Instructions that wait on SCL, these are patched with the SCL Pin number
+// since the IN base is SDA and SCL can be any Pin.
+const WAIT_SCL: [usize; 2] = [5usize, 10usize];
+
+// SET PINDIRS instructions for driving the bus manually. Pins are open-drain
+// (OE is inverted), so a direction of 0 pulls the line low.
+const SC0_SD0: u16 = 0xF780u16; // set pindirs, 0 side 0 [7]
+const SC0_SD1: u16 = 0xF781u16; // set pindirs, 1 side 0 [7]
+const SC1_SD0: u16 = 0xFF80u16; // set pindirs, 0 side 1 [7]
+const SC1_SD1: u16 = 0xFF81u16; // set pindirs, 1 side 1 [7]
+
+// Each 16bit word is either a data record or a count of instructions to
+// execute directly.
+//
+//  | 15 - 10 | 9     | 8 - 1 | 0   |
+//  | Count   | Final | Data  | NAK |
+const FINAL: u16 = 0x200u16;
+const COUNT: u32 = 0xAu32;
+
+const PROGRAM: Program = pio_asm!(
+    .side_set 1 opt;
+    do_nack:
+        jmp y-- entry_point;            // Continue if NAK was expected.
+        irq wait 0 rel;                 // Otherwise stop and flag an error.
+    do_byte:
+        set x, 7;
+    bitloop:
+        out pindirs, 1          [7];    // Write data (all ones when reading).
+        nop             side 1  [2];    // SCL rising edge.
+        wait 1 gpio 0           [4];    // Allow clock stretching.
+        in pins, 1              [7];    // Sample in the middle of SCL high.
+        jmp x-- bitloop side 0  [7];    // SCL falling edge.
+        out pindirs, 1          [7];    // ACK when reading.
+        nop             side 1  [7];    // SCL rising edge.
+        wait 1 gpio 0           [7];    // Allow clock stretching.
+        jmp pin do_nack side 0  [2];    // SDA high is a NAK.
+    .wrap_target;
+    entry_point:
+        out x, 6;                       // Instruction count.
+        out y, 1;                       // NAK ignore (final) bit.
+        jmp !x do_byte;                 // Zero means data record.
+        out null, 32;                   // Discard the rest of the OSR.
+    do_exec:
+        out exec, 16;                   // Execute one instruction per word.
+        jmp x-- do_exec;
+    .wrap;
+);
+const _: () = {
+    assert!(program_is(&PROGRAM, &[
+        0x008Cu16, //  0: jmp y--, 12
+        0xC030,    //  1: irq wait 0 rel
+        0xE027,    //  2: set x, 7
+        0x6781,    //  3: out pindirs, 1         [7]
+        0xBA42,    //  4: nop             side 1 [2]
+        0x2480,    //  5: wait 1 gpio 0          [4]
+        0x4701,    //  6: in pins, 1             [7]
+        0x1743,    //  7: jmp x--, 3      side 0 [7]
+        0x6781,    //  8: out pindirs, 1         [7]
+        0xBF42,    //  9: nop             side 1 [7]
+        0x2780,    // 10: wait 1 gpio 0          [7]
+        0x12C0,    // 11: jmp pin, 0      side 0 [2]
+        0x6026,    // 12: out x, 6
+        0x6041,    // 13: out y, 1
+        0x0022,    // 14: jmp !x, 2
+        0x6060,    // 15: out null, 32
+        0x60F0,    // 16: out exec, 16
+        0x0050,    // 17: jmp x--, 16
+    ]));
+    assert!(PROGRAM.wrap_src == 17 && PROGRAM.wrap_target == 12);
+    // The SCL patch points must be the two 'wait 1 gpio' instructions.
+    let mut i = 0usize;
+    while i < WAIT_SCL.len() {
+        assert!(PROGRAM.code[WAIT_SCL[i]] & 0xE0FF == 0x2080);
+        i += 1;
+    }
+};
+
+pub struct PioI2c<'a> {
+    h:     Handle,
+    tx:    Tx<u32>,
+    rx:    Rx<u32>,
+    sm:    State<'a, Running>,
+    entry: u8,
+}
+
+impl<'a> PioI2c<'a> {
+    pub fn new(p: &Board, s: State<'a, Uninit>, sda: PinID, scl: PinID, freq: u32) -> Result<PioI2c<'a>, PioError> {
+        let mut c = PROGRAM;
+        for i in WAIT_SCL.iter() {
+            c.code[*i] |= scl as u16;
+        }
+        // NOTE(sf): The program goes into the same PIO block as 's' and is
+        //           uninstalled when this is dropped. Use 'stop' to keep it.
+        let h = install_program(s.pio, &c)?;
+        // NOTE(sf): Divisor in 8.8 fixed point, clamped to the range the
+        //           divider supports. See 'CYCLES' for the rates.
+        let d = ((p.system_freq() as u64 * 0x100) / (CYCLES * freq.max(1) as u64)).clamp(0x100, 0xFFFFFF);
+        let mut x = Config::new_with(&h)
+            .sideset_pin(scl)
+            .sideset_as_enable(true)
+            .sideset_as_pin_directions(true)
+            .output_pin(sda)
+            .set_pin(sda)
+            .input_pin(sda)
+            .jump_pin(sda)
+            .pull(true, 16, Shift::Left)
+            .push(true, 8, Shift::Left)
+            .clock_div((d >> 8) as u16, d as u8);
+        // Side-Set count includes the enable bit.
+        x.sideset_pin_count = 2;
+        let mut s = x.configure(s);
+        // NOTE(sf): Avoid glitching the bus when taking the Pins. Drive both
+        //           high first, invert OE so PIO directions act as open-drain,
+        //           then set the output values low for when OE is asserted.
+        s.set_pins_state(PinState::High, &[sda, scl]);
+        s.set_pins_direction(PinDirection::Out, &[sda, scl]);
+        for i in [sda, scl] {
+            i.set_oe_inverted(true);
+            i.set_pull(PinPull::Up);
+        }
+        s.set_pins_state(PinState::Low, &[sda, scl]);
+        let e = h.wrap_target_adjusted();
+        unsafe { s.jump(e) };
+        let sm = s.start();
+        let mut v = PioI2c {
+            tx: sm.tx_u32(),
+            rx: sm.rx_u32(),
+            sm,
+            entry: e,
+            h,
+        };
+        v.clear_error();
+        Ok(v)
+    }
+
+    #[inline]
+    pub fn stop(self) -> (State<'a, Stopped>, Handle) {
+        let v = ManuallyDrop::new(self);
+        unsafe { (read(&v.sm).stop(), read(&v.h)) }
+    }
+    #[inline]
+    pub fn read_single(&mut self, addr: I2cAddress) -> Result<u8, I2cError> {
+        let mut b = [0u8; 1];
+        self.read(addr, &mut b)?;
+        Ok(b[0])
+    }
+    #[inline]
+    pub fn write(&mut self, addr: I2cAddress, b: &[u8]) -> Result<usize, I2cError> {
+        let a = addr_7bit(addr)?;
+        self.cond_start();
+        self.send(a, b);
+        self.finish().map(|_| b.len())
+    }
+    #[inline]
+    pub fn write_single(&mut self, addr: I2cAddress, v: u8) -> Result<(), I2cError> {
+        self.write(addr, &[v]).map(|_| ())
+    }
+    #[inline]
+    pub fn read(&mut self, addr: I2cAddress, b: &mut [u8]) -> Result<usize, I2cError> {
+        let a = addr_7bit(addr)?;
+        self.cond_start();
+        let n = self.recv(a, b);
+        self.finish().map(|_| n)
+    }
+    #[inline]
+    pub fn transfer(&mut self, addr: I2cAddress, input: &[u8], out: &mut [u8]) -> Result<(), I2cError> {
+        let a = addr_7bit(addr)?;
+        self.cond_start();
+        self.send(a, input);
+        self.cond_restart();
+        self.recv(a, out);
+        self.finish()
+    }
+
+    #[inline]
+    fn put(&mut self, v: u16) {
+        // NOTE(sf): OUT shifts left with a 16bit threshold, so the record
+        //           needs to be in the top half of the FIFO word.
+        while self.tx.is_full() {
+            if self.is_error() {
+                return;
+            }
+            nop();
+        }
+        if !self.is_error() {
+            self.tx.write((v as u32) << 16)
+        }
+    }
+    #[inline]
+    fn is_error(&self) -> bool {
+        self.sm.pio().irq().read().irq().bits() & unsafe { 1u8.unchecked_shl(self.sm.idx as u32) } != 0
+    }
+    #[inline]
+    fn cond_stop(&mut self) {
+        self.exec(&[SC0_SD0, SC1_SD0, SC1_SD1])
+    }
+    #[inline]
+    fn cond_start(&mut self) {
+        self.exec(&[SC1_SD0, SC0_SD0])
+    }
+    #[inline]
+    fn clear_error(&mut self) {
+        self.sm
+            .pio()
+            .irq()
+            .write(|r| unsafe { r.irq().bits(1u8.unchecked_shl(self.sm.idx as u32)) })
+    }
+    #[inline]
+    fn cond_restart(&mut self) {
+        self.exec(&[SC0_SD1, SC1_SD1, SC1_SD0, SC0_SD0])
+    }
+    #[inline]
+    fn exec(&mut self, v: &[u16]) {
+        self.put(((v.len() as u32 - 1) << COUNT) as u16);
+        for i in v.iter() {
+            self.put(*i);
+        }
+    }
+    #[inline]
+    fn wait_idle(&mut self) {
+        // Done once the TX FIFO runs dry or the StateMachine flags a NAK.
+        self.tx.clear_stalled();
+        while !self.tx.is_stalled() && !self.is_error() {
+            nop();
+        }
+    }
+    fn finish(&mut self) -> Result<(), I2cError> {
+        self.cond_stop();
+        self.wait_idle();
+        if !self.is_error() {
+            return Ok(());
+        }
+        // NOTE(sf): Drop anything queued, move back to the entry point and
+        //           release the bus. The PIO side can't tell which byte was
+        //           NAK'd, so this is reported as a data NAK.
+        self.sm.drain_fifo();
+        unsafe { self.sm.jump(self.entry) };
+        self.clear_error();
+        self.cond_stop();
+        Err(I2cError::AbortNoAckData)
+    }
+    fn send(&mut self, a: u8, b: &[u8]) {
+        // NOTE(sf): Autopush is always on, so every byte written also pushes
+        //           what was read back off the bus. This has to be drained as
+        //           we go or the StateMachine stalls on a full RX FIFO.
+        while self.rx.try_read_raw().is_some() {}
+        self.put((a as u16) << 2 | 1);
+        let (n, mut i) = (b.len(), 0usize);
+        while i < n && !self.is_error() {
+            if !self.tx.is_full() {
+                let v = unsafe { *b.get_unchecked(i) };
+                i += 1;
+                self.put((v as u16) << 1 | if i == n { FINAL } else { 0 } | 1);
+            }
+            let _ = self.rx.try_read_raw();
+        }
+        self.wait_idle();
+        while self.rx.try_read_raw().is_some() {}
+    }
+    fn recv(&mut self, a: u8, b: &mut [u8]) -> usize {
+        while self.rx.try_read_raw().is_some() {}
+        self.put((a as u16) << 2 | 3);
+        // NOTE(sf): Reads need 0xFF written to clock in each byte. The first
+        //           byte received is the address echo and is skipped. The last
+        //           byte is NAK'd to end the read.
+        let (mut t, mut n, mut f) = (b.len(), 0usize, true);
+        while (t > 0 || n < b.len()) && !self.is_error() {
+            if t > 0 && !self.tx.is_full() {
+                t -= 1;
+                self.put(0x1FE | if t == 0 { FINAL | 1 } else { 0 });
+            }
+            if let Some(v) = self.rx.try_read_raw() {
+                if f {
+                    f = false;
+                    continue;
+                }
+                if let Some(x) = b.get_mut(n) {
+                    *x = v as u8;
+                }
+                n += 1;
+            }
+        }
+        n
+    }
+}
+
+impl Drop for PioI2c<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // Stop the StateMachine before its program goes away.
+        self.sm.set_state(false);
+        uninstall_program(self.sm.pio, self.h.mask)
+    }
+}
+
+#[inline]
+fn addr_7bit(a: I2cAddress) -> Result<u8, I2cError> {
+    if a.is_10bit() || !a.is_valid() { Err(I2cError::InvalidAddress) } else { Ok(a.value() as u8) }
+}
@@ -26,6 +26,7 @@ use core::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use core::marker::{Copy, PhantomData};
 use core::option::Option;
 
+use crate::asm::nop;
 use crate::pac::pio0::RegisterBlock;
 use crate::pio::{Pio, Request};
 use crate::write_reg;
@@ -96,6 +97,12 @@ impl InterruptState {
     }
 }
 impl<'a> Interrupt<'a> {
+    #[inline]
+    pub fn wait(&self, flag: u8) {
+        while !self.try_wait(flag) {
+            nop();
+        }
+    }
     #[inline]
     pub fn raw(&self) -> InterruptState {
         InterruptState(self.ptr().intr().read().bits())
@@ -105,6 +112,15 @@ impl<'a> Interrupt<'a> {
         InterruptState(self.ptr().sm_irq(self.irq as usize).irq_ints().read().bits())
     }
     #[inline]
+    pub fn try_wait(&self, flag: u8) -> bool {
+        let m = unsafe { 1u8.unchecked_shl(flag as u32 & 0x7) };
+        if self.ptr().irq().read().irq().bits() & m == 0 {
+            return false;
+        }
+        self.ptr().irq().write(|r| unsafe { r.irq().bits(m) });
+        true
+    }
+    #[inline]
     pub fn set_interrupt(&self, i: InterruptIndex, en: bool) {
         write_reg(
             self.ptr().sm_irq(self.irq as usize).irq_inte().as_ptr(),
@@ -64,6 +64,17 @@ impl Rx<u8> {
     pub fn try_read(&mut self) -> Option<u8> {
         self.try_read_raw().map(|v| v as u8)
     }
+    pub fn read_all(&mut self, out: &mut [u8]) -> usize {
+        let mut n = 0usize;
+        for i in out.iter_mut() {
+            match self.try_read() {
+                Some(v) => *i = v,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl Tx<u8> {
     #[inline]
@@ -74,6 +85,22 @@ impl Tx<u8> {
     pub fn try_write(&mut self, v: u8) -> Result<(), PioError> {
         self.try_write_raw(v as u32)
     }
+    #[inline]
+    pub fn write_all(&mut self, data: &[u8]) {
+        for i in data.iter() {
+            self.write(*i)
+        }
+    }
+    pub fn write_all_available(&mut self, data: &[u8]) -> usize {
+        let mut n = 0usize;
+        for i in data.iter() {
+            if self.try_write(*i).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl Rx<u16> {
     #[inline]
@@ -84,6 +111,17 @@ impl Rx<u16> {
     pub fn try_read(&mut self) -> Option<u16> {
         self.try_read_raw().map(|v| v as u16)
     }
+    pub fn read_all(&mut self, out: &mut [u16]) -> usize {
+        let mut n = 0usize;
+        for i in out.iter_mut() {
+            match self.try_read() {
+                Some(v) => *i = v,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl Tx<u16> {
     #[inline]
@@ -94,6 +132,22 @@ impl Tx<u16> {
     pub fn try_write(&mut self, v: u16) -> Result<(), PioError> {
         self.try_write_raw(v as u32)
     }
+    #[inline]
+    pub fn write_all(&mut self, data: &[u16]) {
+        for i in data.iter() {
+            self.write(*i)
+        }
+    }
+    pub fn write_all_available(&mut self, data: &[u16]) -> usize {
+        let mut n = 0usize;
+        for i in data.iter() {
+            if self.try_write(*i).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl Rx<u32> {
     #[inline]
@@ -104,6 +158,17 @@ impl Rx<u32> {
     pub fn try_read(&mut self) -> Option<u32> {
         self.try_read_raw()
     }
+    pub fn read_all(&mut self, out: &mut [u32]) -> usize {
+        let mut n = 0usize;
+        for i in out.iter_mut() {
+            match self.try_read() {
+                Some(v) => *i = v,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl Tx<u32> {
     #[inline]
@@ -114,6 +179,22 @@ impl Tx<u32> {
     pub fn try_write(&mut self, v: u32) -> Result<(), PioError> {
         self.try_write_raw(v)
     }
+    #[inline]
+    pub fn write_all(&mut self, data: &[u32]) {
+        for i in data.iter() {
+            self.write(*i)
+        }
+    }
+    pub fn write_all_available(&mut self, data: &[u32]) -> usize {
+        let mut n = 0usize;
+        for i in data.iter() {
+            if self.try_write(*i).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        n
+    }
 }
 impl<T: PioIO> Rx<T> {
     #[inline]
@@ -164,7 +245,7 @@ impl<T: PioIO> Rx<T> {
         self.pio()
             .sm(self.idx as usize)
             .sm_shiftctrl()
-            .write(|r| r.autopush().bit(en));
+            .modify(|_, r| r.autopush().bit(en));
     }
     #[inline]
     pub fn try_read_raw(&mut self) -> Option<u32> {
@@ -255,7 +336,7 @@ impl<T: PioIO> Tx<T> {
         self.pio()
             .sm(self.idx as usize)
             .sm_shiftctrl()
-            .write(|r| r.autopull().bit(en));
+            .modify(|_, r| r.autopull().bit(en));
     }
     #[inline]
     pub fn set_non_empty_irq(&self, i: Request, en: bool) {
@@ -0,0 +1,111 @@
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+
+#![no_implicit_prelude]
+
+extern crate core;
+
+use core::assert;
+use core::mem::ManuallyDrop;
+use core::ops::Drop;
+use core::ptr::read;
+use core::result::Result::{self, Ok};
+
+use crate::Board;
+use crate::pin::{PinDirection, PinID};
+use crate::pio::state::{Running, Stopped, Uninit};
+use crate::pio::{Config, Fifo, Handle, PioError, Program, Shift, State, Tx, install_program, program_is, uninstall_program};
+use crate::pio_asm;
+
+// 800kHz, each bit takes T1 + T2 + T3 (10) PIO cycles.
+const FREQ: u64 = 8_000_000u64;
+
+const PROGRAM: Program = pio_asm!(
+    .side_set 1;
+    .wrap_target;
+    bitloop:
+        out x, 1        side 0 [2]; // T3 - 1
+        jmp !x do_zero  side 1 [1]; // T1 - 1
+        jmp bitloop     side 1 [4]; // T2 - 1
+    do_zero:
+        nop             side 0 [4]; // T2 - 1
+    .wrap;
+);
+const _: () = {
+    assert!(program_is(&PROGRAM, &[0x6221u16, 0x1123, 0x1400, 0xA442]));
+    assert!(PROGRAM.wrap_src == 3 && PROGRAM.wrap_target == 0);
+};
+
+pub struct Ws2812<'a> {
+    h:  Handle,
+    tx: Tx<u32>,
+    sm: State<'a, Running>,
+}
+
+impl<'a> Ws2812<'a> {
+    pub fn new(p: &Board, s: State<'a, Uninit>, pin: PinID) -> Result<Ws2812<'a>, PioError> {
+        // NOTE(sf): The program goes into the same PIO block as 's' and is
+        //           uninstalled when this is dropped. Use 'stop' to keep it.
+        let h = install_program(s.pio, &PROGRAM)?;
+        // Divisor in 8.8 fixed point.
+        let d = (p.system_freq() as u64 * 0x100) / FREQ;
+        let mut s = Config::new_with(&h)
+            .fifo_alloc(Fifo::Tx)
+            .sideset_pins(&[pin])
+            .pull(true, 24, Shift::Left)
+            .clock_div((d >> 8) as u16, d as u8)
+            .configure(s);
+        s.set_pin_direction(PinDirection::Out, pin);
+        let sm = s.start();
+        Ok(Ws2812 { tx: sm.tx_u32(), sm, h })
+    }
+
+    #[inline]
+    pub fn stop(self) -> (State<'a, Stopped>, Handle) {
+        let v = ManuallyDrop::new(self);
+        unsafe { (read(&v.sm).stop(), read(&v.h)) }
+    }
+    #[inline]
+    pub fn write_grb(&mut self, v: u32) {
+        // NOTE(sf): Autopull is at 24 bits and shifts left, so the color
+        //           needs to sit at the top of the word.
+        self.tx.write(v << 8)
+    }
+    #[inline]
+    pub fn write_rgb(&mut self, colors: &[[u8; 3]]) {
+        // NOTE(sf): The strip latches once the line stays low for ~50us after
+        //           the last pixel, so sleep before the next frame.
+        for c in colors.iter() {
+            self.write_grb((c[1] as u32) << 16 | (c[0] as u32) << 8 | c[2] as u32);
+        }
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tx.is_empty()
+    }
+}
+
+impl Drop for Ws2812<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // Stop the StateMachine before its program goes away.
+        self.sm.set_state(false);
+        uninstall_program(self.sm.pio, self.h.mask)
+    }
+}
@@ -29,7 +29,9 @@ use core::ops::{Deref, DerefMut};
 use core::ptr::copy_nonoverlapping;
 
 use crate::Board;
+use crate::pac::ROSC;
 
+pub struct Rng(u32);
 pub struct Rand(u32);
 pub struct RandMut(UnsafeCell<Rand>);
 
@@ -106,6 +108,40 @@ impl Rand {
         n
     }
 }
+impl Rng {
+    #[inline]
+    pub fn new(p: &Board) -> Rng {
+        Rng(p.system_clock().seed())
+    }
+
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        let mut v = 0u32;
+        for _ in 0..32 {
+            v = unsafe { v.unchecked_shl(1) } | random_bit();
+        }
+        // NOTE(sf): Mix the sampled bits with the running state (started from
+        //           the Clock seed) so a stuck or weak ROSC still gives a
+        //           changing output.
+        self.0 = self.0.wrapping_add(0x78BD642F) ^ v;
+        let r = (self.0 as u64).wrapping_mul((self.0 ^ 0xA0B428DB) as u64);
+        (r.wrapping_shr(32) ^ r) as u32
+    }
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        unsafe { (self.next_u32() as u64).unchecked_shl(32) | self.next_u32() as u64 }
+    }
+    pub fn fill_bytes(&mut self, b: &mut [u8]) {
+        let (c, r) = b.as_chunks_mut::<4>();
+        for i in c {
+            *i = self.next_u32().to_be_bytes();
+        }
+        if !r.is_empty() {
+            let v = self.next_u32().to_be_bytes();
+            r.copy_from_slice(&v[0..r.len()]);
+        }
+    }
+}
 impl RandMut {
     #[inline]
     pub const fn empty() -> RandMut {
@@ -186,3 +222,49 @@ impl DerefMut for RandMut {
         unsafe { &mut *self.0.get() }
     }
 }
+
+#[inline]
+fn random_bit() -> u32 {
+    let r = unsafe { ROSC::steal() };
+    // NOTE(sf): The RANDOMBIT sample is biased depending on the ROSC drive, so
+    //           we use a von Neumann debias step. Take two samples and only keep
+    //           the first when they differ ('01' => 0, '10' => 1), otherwise
+    //           throw both away and try again.
+    loop {
+        let (a, b) = (r.randombit().read().randombit().bit(), r.randombit().read().randombit().bit());
+        if a != b {
+            return a as u32;
+        }
+    }
+}
+
+#[cfg(feature = "rand_core")]
+mod rng {
+    extern crate core;
+    extern crate rand_core;
+
+    use core::result::Result::{self, Ok};
+
+    use rand_core::{Error, RngCore};
+
+    use crate::rand::Rng;
+
+    impl RngCore for Rng {
+        #[inline]
+        fn next_u32(&mut self) -> u32 {
+            Rng::next_u32(self)
+        }
+        #[inline]
+        fn next_u64(&mut self) -> u64 {
+            Rng::next_u64(self)
+        }
+        #[inline]
+        fn fill_bytes(&mut self, b: &mut [u8]) {
+            Rng::fill_bytes(self, b)
+        }
+        #[inline]
+        fn try_fill_bytes(&mut self, b: &mut [u8]) -> Result<(), Error> {
+            Ok(Rng::fill_bytes(self, b))
+        }
+    }
+}
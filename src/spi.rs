@@ -21,24 +21,32 @@
 
 extern crate core;
 
+use core::assert;
 use core::cmp::Ord;
 use core::convert::{From, TryFrom};
 use core::default::Default;
 use core::fmt::{self, Debug, Formatter};
 use core::iter::Iterator;
 use core::marker::{PhantomData, Send};
+use core::mem::ManuallyDrop;
 use core::matches;
-use core::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut, Drop};
 use core::option::Option::{self, None, Some};
 use core::ptr::NonNull;
 use core::result::Result::{self, Err, Ok};
 
 use crate::Board;
 use crate::asm::nop;
-use crate::dma::{DmaReader, DmaWriter};
+use crate::dma::mode::Single;
+use crate::dma::{Dma, DmaMem, DmaMemMut, DmaPace, DmaReader, DmaSingle, DmaStream, DmaWriter};
 use crate::pac::spi0::RegisterBlock;
 use crate::pac::{RESETS, SPI0, SPI1};
-use crate::pin::{PinFunction, PinID, SpiID, pins_spi};
+use crate::pin::gpio::Output;
+use crate::pin::{Pin, PinFunction, PinID, SpiID, pins_spi, valid_spi};
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+#[cfg(feature = "embedded-hal")]
+pub use self::hal::*;
 
 pub enum SpiError {
     WouldBlock,
@@ -68,6 +76,20 @@ pub enum SpiBus<'a> {
 
 pub struct Spi {
     dev: NonNull<RegisterBlock>,
+    rev: u8,
+    own: bool,
+}
+pub struct SpiDma<'a> {
+    n:     usize,
+    rx:    ManuallyDrop<DmaStream<Single<u8, Spi, DmaMemMut<'a, u8>>>>,
+    tx:    ManuallyDrop<DmaStream<Single<u8, DmaMem<'a, u8>, Spi>>>,
+    dev:   NonNull<RegisterBlock>,
+    rx_ch: Dma,
+    _p:    PhantomData<&'a mut Spi>,
+}
+pub struct SpiTransaction<'a> {
+    cs:  &'a Pin<Output>,
+    spi: &'a mut Spi,
 }
 pub struct SpiDev {
     pub tx:  PinID,
@@ -110,23 +132,7 @@ pub trait SpiShort: SpiIO<u16> {}
 
 impl Spi {
     pub fn new(p: &Board, baudrate: u32, cfg: SpiConfig, d: SpiDev) -> Result<Spi, SpiError> {
-        let (b, mut k) = (p.system_freq(), 0xFFu8);
-        for i in (2..=0xFE).step_by(2) {
-            if b < ((i + 2) * 0x100u32).saturating_mul(baudrate) {
-                k = i as u8;
-                break;
-            }
-        }
-        if k == u8::MAX {
-            return Err(SpiError::InvalidFrequency);
-        }
-        let mut j = 0u8;
-        for i in (1..=0xFF).rev() {
-            if b / (k as u32 * i as u32) > baudrate {
-                j = i;
-                break;
-            }
-        }
+        let (k, j) = calc_dvs(baudrate, p.peripheral_freq())?;
         let v = d.device().ok_or(SpiError::InvalidPins)?;
         unsafe {
             let t = &*v;
@@ -168,6 +174,8 @@ impl Spi {
         }
         Ok(Spi {
             dev: unsafe { NonNull::new_unchecked(v as *mut RegisterBlock) },
+            rev: 0u8,
+            own: true,
         })
     }
 
@@ -193,13 +201,185 @@ impl Spi {
     pub fn is_readable(&self) -> bool {
         self.ptr().sspsr().read().rne().bit_is_set()
     }
+    pub fn recv(&mut self, b: &mut [u8]) -> usize {
+        let (mut n, p) = (0usize, self.ptr());
+        while n < b.len() && self.is_readable() {
+            unsafe { *b.get_unchecked_mut(n) = self.order8(p.sspdr().read().data().bits() as u8) };
+            n += 1;
+        }
+        n
+    }
+    pub fn preload(&mut self, b: &[u8]) -> usize {
+        let (mut n, p) = (0usize, self.ptr());
+        // NOTE(sf): In secondary mode the controller drives the clock, so the
+        //           TX FIFO has to be filled before it starts clocking us.
+        while n < b.len() && self.is_writable() {
+            p.sspdr().write(|r| unsafe { r.data().bits(self.order8(*b.get_unchecked(n)) as u16) });
+            n += 1;
+        }
+        n
+    }
+    #[inline]
+    // NOTE(sf): 'peri_freq' is the peripheral clock (clk_peri) that feeds the
+    //           SPI, see 'Board::peripheral_freq'.
+    pub fn baudrate(&self, peri_freq: u32) -> u32 {
+        let p = self.ptr();
+        let (k, j) = (
+            p.sspcpsr().read().cpsdvsr().bits() as u32,
+            p.sspcr0().read().scr().bits() as u32,
+        );
+        peri_freq.checked_div(k * (j + 1)).unwrap_or(0)
+    }
+    pub fn set_baudrate(&mut self, baud: u32, peri_freq: u32) -> Result<(), SpiError> {
+        let (k, j) = calc_dvs(baud, peri_freq)?;
+        self.flush();
+        let p = self.ptr();
+        let e = p.sspcr1().read().sse().bit_is_set();
+        p.sspcr1().modify(|_, r| r.sse().clear_bit());
+        p.sspcpsr().write(|r| unsafe { r.cpsdvsr().bits(k) });
+        p.sspcr0().modify(|_, r| unsafe { r.scr().bits(j) });
+        p.sspcr1().modify(|_, r| r.sse().bit(e));
+        Ok(())
+    }
+    #[inline]
+    pub fn transfer_dma(&mut self, tx_ch: Dma, rx_ch: Dma, input: &[u8], output: &mut [u8]) -> usize {
+        match self.transfer_dma_start(tx_ch, rx_ch, input, output) {
+            Some(v) => v.wait(),
+            None => 0,
+        }
+    }
+    pub fn transfer_dma_start<'a>(&'a mut self, tx_ch: Dma, rx_ch: Dma, input: &'a [u8], output: &'a mut [u8]) -> Option<SpiDma<'a>> {
+        let n = input.len().min(output.len());
+        // NOTE(sf): A zero transfer count would never raise the DMA IRQ, so
+        //           there's nothing to start.
+        if n == 0 {
+            return None;
+        }
+        // Drop anything left over in the RX FIFO.
+        while self.is_readable() {
+            let _ = self.ptr().sspdr().read();
+        }
+        self.ptr()
+            .sspdmacr()
+            .modify(|_, r| r.txdmae().set_bit().rxdmae().set_bit());
+        // NOTE(sf): RX has to be started first so it's ready for the first
+        //           byte clocked out by TX. Each channel is paced by it's SPI
+        //           DREQ.
+        let r = DmaSingle::<u8, Spi, DmaMemMut<'_, u8>>::new(rx_ch, Spi { dev: self.dev, rev: 0u8, own: false }, DmaMemMut::new(&mut output[0..n])).start();
+        let mut t = DmaSingle::<u8, DmaMem<'_, u8>, Spi>::new(tx_ch, DmaMem::new(&input[0..n]), Spi { dev: self.dev, rev: 0u8, own: false });
+        t.pace(DmaPace::Sink);
+        Some(SpiDma {
+            n,
+            rx_ch,
+            rx: ManuallyDrop::new(r),
+            tx: ManuallyDrop::new(t.start()),
+            dev: self.dev,
+            _p: PhantomData,
+        })
+    }
+    #[inline]
+    pub fn set_bit_reverse(&mut self, en: bool) {
+        // NOTE(sf): The PL022 is MSB-first only, so this reverses each frame in
+        //           software on the CPU read/write paths. DMA transfers are
+        //           NOT reversed.
+        self.rev = if en { self.ptr().sspcr0().read().dss().bits() + 1 } else { 0u8 }
+    }
+    #[inline]
+    pub fn set_loopback(&mut self, en: bool) {
+        self.flush();
+        self.ptr().sspcr1().modify(|_, r| r.lbm().bit(en))
+    }
+    pub fn self_test(&mut self) -> bool {
+        // NOTE(sf): Loopback still needs us to drive the clock, so this only
+        //           works in primary mode. Nothing is driven on the pins.
+        if self.ptr().sspcr1().read().ms().bit_is_set() {
+            return false;
+        }
+        let e = self.ptr().sspcr1().read().lbm().bit_is_set();
+        self.set_loopback(true);
+        while self.is_readable() {
+            let _ = self.ptr().sspdr().read();
+        }
+        let m = u16::MAX.wrapping_shr(15 - self.ptr().sspcr0().read().dss().bits() as u32);
+        let mut r = true;
+        for i in [0xA5A5u16, 0x5A5A, 0xFFFF, 0x0000, 0x1234] {
+            if SpiIO::<u16>::transfer_single(self, i & m) != i & m {
+                r = false;
+                break;
+            }
+        }
+        self.set_loopback(e);
+        r
+    }
+    #[inline]
+    pub fn transaction<'a>(&'a mut self, cs: &'a Pin<Output>) -> SpiTransaction<'a> {
+        SpiTransaction::new(self, cs)
+    }
 
     #[inline]
     fn ptr(&self) -> &RegisterBlock {
         unsafe { self.dev.as_ref() }
     }
+    #[inline]
+    fn order8(&self, v: u8) -> u8 {
+        if self.rev == 0 { v } else { v.reverse_bits().wrapping_shr(8u32.saturating_sub(self.rev as u32)) }
+    }
+    #[inline]
+    fn order16(&self, v: u16) -> u16 {
+        if self.rev == 0 { v } else { v.reverse_bits().wrapping_shr(16u32.saturating_sub(self.rev as u32)) }
+    }
+}
+impl SpiDma<'_> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.n
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+    #[inline]
+    pub fn wait(self) -> usize {
+        let mut v = ManuallyDrop::new(self);
+        let (t, r) = unsafe { (ManuallyDrop::take(&mut v.tx), ManuallyDrop::take(&mut v.rx)) };
+        t.wait();
+        r.wait();
+        v.n
+    }
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.tx.is_done() && self.rx.is_done()
+    }
+    #[inline]
+    pub fn set_irq(&self, which: u8, en: bool) {
+        // NOTE(sf): RX always finishes last, so it's the only channel that
+        //           needs to fire.
+        self.rx_ch.set_irq(which, en)
+    }
+    #[inline]
+    pub fn transferred(&self) -> usize {
+        self.rx.transferred() as usize
+    }
+}
+impl<'a> SpiTransaction<'a> {
+    #[inline]
+    pub fn new(spi: &'a mut Spi, cs: &'a Pin<Output>) -> SpiTransaction<'a> {
+        // NOTE(sf): In primary mode the hardware CS is only held for a single
+        //           frame, so we drive it ourselves for the whole transaction.
+        cs.low();
+        SpiTransaction { cs, spi }
+    }
 }
 impl SpiDev {
+    #[inline]
+    pub const fn checked(tx: PinID, sck: PinID, rx: Option<PinID>, cs: Option<PinID>) -> SpiDev {
+        // NOTE(sf): This is only a compile error when evaluated in a const
+        //           context, ie: 'const D: SpiDev = SpiDev::checked(..);'.
+        //           At runtime it just panics, use 'new' to get an error.
+        assert!(valid_spi(tx, sck, rx, cs), "invalid SPI pins");
+        SpiDev { tx, sck, cs, rx }
+    }
+
     #[inline]
     pub fn new(tx: PinID, sck: PinID) -> Result<SpiDev, SpiError> {
         let d = SpiDev { tx, sck, cs: None, rx: None };
@@ -355,6 +535,60 @@ impl DerefMut for SpiBus<'_> {
         }
     }
 }
+impl Deref for SpiTransaction<'_> {
+    type Target = Spi;
+
+    #[inline]
+    fn deref(&self) -> &Spi {
+        self.spi
+    }
+}
+impl DerefMut for SpiTransaction<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Spi {
+        self.spi
+    }
+}
+impl Drop for Spi {
+    #[inline]
+    fn drop(&mut self) {
+        // NOTE(sf): Only the Spi returned by 'new' owns the device, copies made
+        //           for 'SpiBus::Duplicated' or DMA transfers do not.
+        if !self.own {
+            return;
+        }
+        let r = unsafe { RESETS::steal() };
+        if self.dev.as_ptr().addr() == SPI0::PTR.addr() {
+            r.reset().modify(|_, r| r.spi0().set_bit());
+        } else {
+            r.reset().modify(|_, r| r.spi1().set_bit());
+        }
+    }
+}
+impl Drop for SpiDma<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // NOTE(sf): Stop TX first so nothing else gets clocked out, then RX so
+        //           the output buffer isn't written once the borrow ends. Any
+        //           frames that made it into the RX FIFO are dropped.
+        unsafe {
+            ManuallyDrop::drop(&mut self.tx);
+            ManuallyDrop::drop(&mut self.rx);
+        }
+        let mut s = Spi { dev: self.dev, rev: 0u8, own: false };
+        s.flush();
+        while s.is_readable() {
+            let _ = s.ptr().sspdr().read();
+        }
+    }
+}
+impl Drop for SpiTransaction<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.spi.flush();
+        self.cs.high();
+    }
+}
 impl<'a> From<Spi> for SpiBus<'a> {
     #[inline]
     fn from(v: Spi) -> SpiBus<'a> {
@@ -364,7 +598,7 @@ impl<'a> From<Spi> for SpiBus<'a> {
 impl<'a> From<&'a Spi> for SpiBus<'a> {
     #[inline]
     fn from(v: &'a Spi) -> SpiBus<'a> {
-        SpiBus::Duplicated((Spi { dev: v.dev }, PhantomData))
+        SpiBus::Duplicated((Spi { dev: v.dev, rev: v.rev, own: false }, PhantomData))
     }
 }
 impl<'a> From<&'a mut Spi> for SpiBus<'a> {
@@ -392,9 +626,10 @@ impl Debug for SpiError {
 }
 
 unsafe impl Send for Spi {}
+unsafe impl Send for SpiDma<'_> {}
 
 macro_rules! spi_io {
-    ($ty:ty) => {
+    ($ty:ty, $order:ident) => {
         impl SpiIO<$ty> for Spi {
             fn write(&mut self, b: &[$ty]) {
                 let p = self.ptr();
@@ -402,7 +637,7 @@ macro_rules! spi_io {
                     while p.sspsr().read().tnf().bit_is_clear() {
                         nop();
                     }
-                    p.sspdr().write(|r| unsafe { r.data().bits(*i as _) });
+                    p.sspdr().write(|r| unsafe { r.data().bits(self.$order(*i) as _) });
                     while p.sspsr().read().rne().bit_is_clear() {
                         nop();
                     }
@@ -411,18 +646,18 @@ macro_rules! spi_io {
             }
             #[inline]
             fn recv_single(&mut self) -> Option<$ty> {
-                if self.is_readable() { Some(self.ptr().sspdr().read().data().bits() as _) } else { None }
+                if self.is_readable() { Some(self.$order(self.ptr().sspdr().read().data().bits() as _)) } else { None }
             }
             fn transfer_single(&mut self, v: $ty) -> $ty {
                 let p = self.ptr();
                 while p.sspsr().read().tnf().bit_is_clear() {
                     nop();
                 }
-                p.sspdr().write(|r| unsafe { r.data().bits(v as _) });
+                p.sspdr().write(|r| unsafe { r.data().bits(self.$order(v) as _) });
                 while p.sspsr().read().rne().bit_is_clear() {
                     nop();
                 }
-                p.sspdr().read().data().bits() as _
+                self.$order(p.sspdr().read().data().bits() as _)
             }
             fn read_with(&mut self, v: $ty, b: &mut [$ty]) {
                 let p = self.ptr();
@@ -430,11 +665,11 @@ macro_rules! spi_io {
                     while p.sspsr().read().tnf().bit_is_clear() {
                         nop();
                     }
-                    p.sspdr().write(|r| unsafe { r.data().bits(v as _) });
+                    p.sspdr().write(|r| unsafe { r.data().bits(self.$order(v) as _) });
                     while p.sspsr().read().rne().bit_is_clear() {
                         nop();
                     }
-                    *i = p.sspdr().read().data().bits() as _;
+                    *i = self.$order(p.sspdr().read().data().bits() as _);
                 }
             }
             fn transfer_in_place(&mut self, b: &mut [$ty]) {
@@ -443,11 +678,11 @@ macro_rules! spi_io {
                     while p.sspsr().read().tnf().bit_is_clear() {
                         nop();
                     }
-                    p.sspdr().write(|r| unsafe { r.data().bits(*i as _) });
+                    p.sspdr().write(|r| unsafe { r.data().bits(self.$order(*i) as _) });
                     while p.sspsr().read().rne().bit_is_clear() {
                         nop();
                     }
-                    *i = p.sspdr().read().data().bits() as _;
+                    *i = self.$order(p.sspdr().read().data().bits() as _);
                 }
             }
             #[inline]
@@ -455,7 +690,7 @@ macro_rules! spi_io {
                 if !self.is_writable() {
                     return Err(SpiError::WouldBlock);
                 }
-                self.ptr().sspdr().write(|r| unsafe { r.data().bits(v as _ ) });
+                self.ptr().sspdr().write(|r| unsafe { r.data().bits(self.$order(v) as _) });
                 Ok(())
             }
             fn transfer(&mut self, input: &[$ty], out: &mut [$ty]) -> usize {
@@ -464,11 +699,11 @@ macro_rules! spi_io {
                     while p.sspsr().read().tnf().bit_is_clear() {
                         nop();
                     }
-                    p.sspdr().write(|r| unsafe { r.data().bits(*input.get_unchecked(i) as _) });
+                    p.sspdr().write(|r| unsafe { r.data().bits(self.$order(*input.get_unchecked(i)) as _) });
                     while p.sspsr().read().rne().bit_is_clear() {
                         nop();
                     }
-                    unsafe { *out.get_unchecked_mut(i) = p.sspdr().read().data().bits() as _ };
+                    unsafe { *out.get_unchecked_mut(i) = self.$order(p.sspdr().read().data().bits() as _) };
                 }
                 n
             }
@@ -505,5 +740,142 @@ macro_rules! spi_io {
     };
 }
 
-spi_io!(u8);
-spi_io!(u16);
+spi_io!(u8, order8);
+spi_io!(u16, order16);
+
+fn calc_dvs(w: u32, f: u32) -> Result<(u8, u8), SpiError> {
+    let mut k = 0xFFu8;
+    for i in (2..=0xFE).step_by(2) {
+        if f < ((i + 2) * 0x100u32).saturating_mul(w) {
+            k = i as u8;
+            break;
+        }
+    }
+    if k == u8::MAX {
+        return Err(SpiError::InvalidFrequency);
+    }
+    let mut j = 0u8;
+    for i in (1..=0xFF).rev() {
+        if f / (k as u32 * i as u32) > w {
+            j = i;
+            break;
+        }
+    }
+    Ok((k, j))
+}
+
+#[cfg(feature = "embedded-hal")]
+mod hal {
+    extern crate core;
+    extern crate embedded_hal;
+
+    use core::clone::Clone;
+    use core::convert::Into;
+    use core::result::Result::{self, Ok};
+
+    use self::embedded_hal::spi::{Error, ErrorKind, ErrorType, Operation, SpiBus as HalBus, SpiDevice};
+    use crate::clock::Timer;
+    use crate::pin::Pin;
+    use crate::pin::gpio::Output;
+    use crate::spi::{Spi, SpiBus, SpiError, SpiIO};
+
+    pub struct SpiExclusive<'a> {
+        cs:    Pin<Output>,
+        bus:   SpiBus<'a>,
+        timer: Timer,
+    }
+
+    impl<'a> SpiExclusive<'a> {
+        #[inline]
+        pub fn new(bus: impl Into<SpiBus<'a>>, cs: Pin<Output>, timer: &Timer) -> SpiExclusive<'a> {
+            cs.high();
+            SpiExclusive {
+                cs,
+                bus: bus.into(),
+                timer: timer.clone(),
+            }
+        }
+
+        #[inline]
+        pub fn free(self) -> (SpiBus<'a>, Pin<Output>) {
+            (self.bus, self.cs)
+        }
+    }
+
+    impl Error for SpiError {
+        #[inline]
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    impl ErrorType for Spi {
+        type Error = SpiError;
+    }
+    impl ErrorType for SpiExclusive<'_> {
+        type Error = SpiError;
+    }
+
+    macro_rules! spi_hal {
+        ($ty:ty) => {
+            impl HalBus<$ty> for Spi {
+                #[inline]
+                fn flush(&mut self) -> Result<(), SpiError> {
+                    Spi::flush(self);
+                    Ok(())
+                }
+                #[inline]
+                fn read(&mut self, b: &mut [$ty]) -> Result<(), SpiError> {
+                    SpiIO::<$ty>::read(self, b);
+                    Ok(())
+                }
+                #[inline]
+                fn write(&mut self, b: &[$ty]) -> Result<(), SpiError> {
+                    SpiIO::<$ty>::write(self, b);
+                    Ok(())
+                }
+                #[inline]
+                fn transfer_in_place(&mut self, b: &mut [$ty]) -> Result<(), SpiError> {
+                    SpiIO::<$ty>::transfer_in_place(self, b);
+                    Ok(())
+                }
+                fn transfer(&mut self, read: &mut [$ty], write: &[$ty]) -> Result<(), SpiError> {
+                    let n = SpiIO::<$ty>::transfer(self, write, read);
+                    // NOTE(sf): The shorter side gets padded, extra reads are
+                    //           clocked with zeros and extra writes are dropped.
+                    if n < write.len() {
+                        SpiIO::<$ty>::write(self, unsafe { write.get_unchecked(n..) });
+                    }
+                    if n < read.len() {
+                        SpiIO::<$ty>::read(self, unsafe { read.get_unchecked_mut(n..) });
+                    }
+                    Ok(())
+                }
+            }
+
+            impl SpiDevice<$ty> for SpiExclusive<'_> {
+                fn transaction(&mut self, ops: &mut [Operation<'_, $ty>]) -> Result<(), SpiError> {
+                    self.cs.low();
+                    for i in ops.iter_mut() {
+                        match i {
+                            Operation::Read(b) => HalBus::<$ty>::read(&mut *self.bus, b)?,
+                            Operation::Write(b) => HalBus::<$ty>::write(&mut *self.bus, b)?,
+                            Operation::Transfer(r, w) => HalBus::<$ty>::transfer(&mut *self.bus, r, w)?,
+                            Operation::TransferInPlace(b) => HalBus::<$ty>::transfer_in_place(&mut *self.bus, b)?,
+                            Operation::DelayNs(v) => {
+                                self.bus.flush();
+                                self.timer.sleep_us(v.div_ceil(1_000));
+                            },
+                        }
+                    }
+                    self.bus.flush();
+                    self.cs.high();
+                    Ok(())
+                }
+            }
+        };
+    }
+
+    spi_hal!(u8);
+    spi_hal!(u16);
+}
@@ -20,10 +20,16 @@
 #![no_implicit_prelude]
 
 extern crate core;
+extern crate cortex_m;
 
 use core::clone::Clone;
 use core::marker::Copy;
+use core::ptr::write_volatile;
 
+use cortex_m::interrupt::free;
+
+use crate::asm::nop;
+use crate::flash::{Rom, flash_cmd};
 use crate::pac::VREG_AND_CHIP_RESET;
 
 #[repr(u8)]
@@ -57,11 +63,47 @@ pub fn voltage() -> Voltage {
         _ => Voltage::Volts0_80,
     }
 }
+// NOTE(sf): The ID is read from the flash chip with the 'READ_UNIQUE_ID' (0x4B)
+//           command, followed by 4 dummy bytes. XIP is disabled while the
+//           command runs, so core1 must not be running from flash.
+pub fn unique_id() -> [u8; 8] {
+    let (r, mut b) = (Rom::get(), [0u8; 13]);
+    b[0] = 0x4B;
+    free(|_| unsafe { flash_cmd(&r, &mut b) });
+    let mut v = [0u8; 8];
+    v.copy_from_slice(&b[5..]);
+    v
+}
 #[inline]
 pub fn set_voltage(v: Voltage) {
-    unsafe { VREG_AND_CHIP_RESET::steal() }
-        .vreg()
-        .write(|r| unsafe { r.vsel().bits(v as u8) });
+    let r = unsafe { VREG_AND_CHIP_RESET::steal() };
+    // NOTE(sf): Raise this before running the PLL above 133MHz.
+    r.vreg().modify(|_, x| unsafe { x.vsel().bits(v as u8) });
+    // Wait for the regulator to settle.
+    while r.vreg().read().rok().bit_is_clear() {
+        nop();
+    }
+}
+// NOTE(sf): Every peripheral register has three aliases that apply the write
+//           atomically, so no read-modify-write (and no race with the other
+//           core) is needed. They're at the register address plus:
+//
+//           0x1000: XOR the bits in the value.
+//           0x2000: Set the bits in the value.
+//           0x3000: Clear the bits in the value.
+//
+//           These only work for APB/AHB peripheral registers, not SIO.
+#[inline]
+pub unsafe fn atomic_set(reg: *mut u32, mask: u32) {
+    unsafe { write_volatile((reg as usize + 0x2000) as *mut u32, mask) }
+}
+#[inline]
+pub unsafe fn atomic_xor(reg: *mut u32, mask: u32) {
+    unsafe { write_volatile((reg as usize + 0x1000) as *mut u32, mask) }
+}
+#[inline]
+pub unsafe fn atomic_clear(reg: *mut u32, mask: u32) {
+    unsafe { write_volatile((reg as usize + 0x3000) as *mut u32, mask) }
 }
 
 impl Copy for Voltage {}
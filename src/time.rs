@@ -21,13 +21,25 @@
 
 extern crate core;
 
+use core::assert;
 use core::clone::Clone;
 use core::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use core::convert::From;
 use core::default::Default;
 use core::marker::Copy;
-use core::ops::FnOnce;
-use core::option::Option;
+use core::ops::{Add, AddAssign, FnOnce, Sub, SubAssign};
+use core::option::Option::{self, None, Some};
+
+use crate::clock::Timer;
+
+// NOTE(sf): Value of 'Time::into_seconds' for 1970-01-01 00:00:00.
+const UNIX_EPOCH: i64 = 0x7FFFFFFE1AD9C900i64;
+// NOTE(sf): Unix bounds for 0000-01-01 00:00:00 and 2226-12-31 23:59:59. The
+//           RTC has 12 bits for the year, but 'into_seconds' overflows i64
+//           past 2227-12-06 15:30:07 due to the 'UNIX_EPOCH' offset.
+const UNIX_MIN: i64 = -0xE79747C00i64;
+const UNIX_MAX: i64 = 0x1E366707Fi64;
+const YEAR_MAX: u16 = 0x8B2u16;
 
 const DAYS_IN_YEAR: [u16; 13] = [
     0, 0x1F, 0x3B, 0x5A, 0x78, 0x97, 0xB5, 0xD4, 0xF3, 0x111, 0x130, 0x14E, 0x16D,
@@ -70,6 +82,7 @@ pub struct Time {
     pub month:   Month,
     pub weekday: Weekday,
 }
+pub struct Instant(u64);
 
 impl Time {
     #[inline]
@@ -110,7 +123,18 @@ impl Time {
     }
 
     #[inline]
-    pub fn from_seconds(sec: i64) -> Time {
+    pub const fn from_unix(secs: i64) -> Time {
+        let v = if secs < UNIX_MIN {
+            UNIX_MIN
+        } else if secs > UNIX_MAX {
+            UNIX_MAX
+        } else {
+            secs
+        };
+        Time::from_seconds(v.saturating_add(UNIX_EPOCH))
+    }
+    #[inline]
+    pub const fn from_seconds(sec: i64) -> Time {
         let (h, m, s) = clock(sec);
         let (y, v, d) = date(sec);
         Time::new(
@@ -120,35 +144,114 @@ impl Time {
             h,
             m,
             s,
-            Weekday::from(((sec.wrapping_add(0x15180) & 0x93A80) / 0x15180) as u8),
+            weekday(((sec.wrapping_add(0x15180) % 0x93A80) / 0x15180) as u8),
         )
     }
 
     #[inline]
-    pub fn is_valid(&self) -> bool {
-        self.day >= 1 && self.day <= 31 && self.hours <= 23 && self.mins <= 59 && self.secs <= 59 && !self.month.is_none()
+    pub const fn day_of_year(&self) -> u16 {
+        let m = match self.month as usize {
+            0 => 1,
+            v => v,
+        };
+        let d = DAYS_IN_YEAR[m - 1] + self.day as u16;
+        if m >= 3 && is_leap(self.year) { d + 1 } else { d }
     }
-    pub fn into_seconds(&self) -> i64 {
+    #[inline]
+    pub const fn iso_weekday(&self) -> u8 {
+        match Weekday::from_time(self) {
+            Weekday::Sunday => 7,
+            v => v as u8,
+        }
+    }
+    #[inline]
+    pub const fn is_valid(&self) -> bool {
+        self.day >= 1
+            && self.day <= 31
+            && self.hours <= 23
+            && self.mins <= 59
+            && self.secs <= 59
+            && self.year <= YEAR_MAX
+            && !self.month.is_none()
+    }
+    pub const fn into_seconds(&self) -> i64 {
         let (y, v) = norm(self.year as i32, self.month as i32 - 1, 0xC);
         let (s, _) = norm(self.secs as i32, 0, 0x3B9ACA00);
         let (m, s) = norm(self.mins as i32, s, 0x3C);
         let (h, m) = norm(self.hours as i32, m, 0x3C);
         let (d, h) = norm(self.day as i32, h, 0x18);
         let r = v as usize + 1;
-        let mut e = since_epoch(y) + unsafe { *DAYS_IN_YEAR.get_unchecked((r - 1).min(12)) as i64 };
+        let mut e = since_epoch(y) + DAYS_IN_YEAR[if r > 13 { 12 } else { r - 1 }] as i64;
         if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) && r >= 3 {
             e += 1;
         }
-        ((e + d as i64 - 1) * 0x15180) + (h * 0xE10 + m * 0x3C + s) as i64
+        (e + d as i64 - 1)
+            .saturating_mul(0x15180)
+            .saturating_add((h * 0xE10 + m * 0x3C + s) as i64)
+    }
+    #[inline]
+    pub const fn into_unix(&self) -> i64 {
+        self.into_seconds().saturating_sub(UNIX_EPOCH)
     }
     #[inline]
     pub fn add_seconds(self, d: i64) -> Time {
         Time::from_seconds(self.into_seconds().wrapping_add(d))
     }
 }
+impl Instant {
+    pub const ZERO: Instant = Instant(0u64);
+
+    #[inline]
+    pub fn now(t: &Timer) -> Instant {
+        Instant(t.current_tick())
+    }
+    #[inline]
+    pub const fn from_micros(v: u64) -> Instant {
+        Instant(v)
+    }
+
+    #[inline]
+    pub const fn as_micros(&self) -> u64 {
+        self.0
+    }
+    #[inline]
+    pub const fn as_millis(&self) -> u64 {
+        self.0 / 1_000
+    }
+    #[inline]
+    pub fn elapsed(&self, t: &Timer) -> u64 {
+        t.current_tick().saturating_sub(self.0)
+    }
+    #[inline]
+    pub fn has_elapsed(&self, t: &Timer, us: u64) -> bool {
+        self.elapsed(t) >= us
+    }
+    #[inline]
+    pub const fn duration_since(&self, earlier: Instant) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+    #[inline]
+    pub const fn checked_add(&self, us: u64) -> Option<Instant> {
+        match self.0.checked_add(us) {
+            Some(v) => Some(Instant(v)),
+            None => None,
+        }
+    }
+    #[inline]
+    pub const fn checked_sub(&self, us: u64) -> Option<Instant> {
+        match self.0.checked_sub(us) {
+            Some(v) => Some(Instant(v)),
+            None => None,
+        }
+    }
+    #[inline]
+    pub const fn checked_duration_since(&self, earlier: Instant) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+}
 impl Month {
     #[inline]
-    pub fn is_none(&self) -> bool {
+    pub const fn is_none(&self) -> bool {
         match self {
             Month::None => true,
             _ => false,
@@ -164,16 +267,16 @@ impl Month {
 }
 impl Weekday {
     #[inline]
-    pub fn from_time(t: &Time) -> Weekday {
+    pub const fn from_time(t: &Time) -> Weekday {
         if !t.weekday.is_none() {
             t.weekday
         } else {
-            Weekday::from(((t.into_seconds().wrapping_add(0x15180) & 0x93A80) / 0x15180) as u8)
+            weekday(((t.into_seconds().wrapping_add(0x15180) % 0x93A80) / 0x15180) as u8)
         }
     }
 
     #[inline]
-    pub fn is_none(&self) -> bool {
+    pub const fn is_none(&self) -> bool {
         match self {
             Weekday::None => true,
             _ => false,
@@ -217,6 +320,75 @@ impl PartialEq for Time {
     }
 }
 
+impl Eq for Instant {}
+impl Ord for Instant {
+    #[inline]
+    fn cmp(&self, other: &Instant) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+impl Copy for Instant {}
+impl Clone for Instant {
+    #[inline]
+    fn clone(&self) -> Instant {
+        Instant(self.0)
+    }
+}
+impl Default for Instant {
+    #[inline]
+    fn default() -> Instant {
+        Instant::ZERO
+    }
+}
+impl Add<u64> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn add(self, rhs: u64) -> Instant {
+        Instant(self.0.saturating_add(rhs))
+    }
+}
+impl Sub<u64> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn sub(self, rhs: u64) -> Instant {
+        Instant(self.0.saturating_sub(rhs))
+    }
+}
+impl Sub<Instant> for Instant {
+    type Output = u64;
+
+    #[inline]
+    fn sub(self, rhs: Instant) -> u64 {
+        self.0.saturating_sub(rhs.0)
+    }
+}
+impl PartialEq for Instant {
+    #[inline]
+    fn eq(&self, other: &Instant) -> bool {
+        self.0 == other.0
+    }
+}
+impl AddAssign<u64> for Instant {
+    #[inline]
+    fn add_assign(&mut self, rhs: u64) {
+        self.0 = self.0.saturating_add(rhs)
+    }
+}
+impl SubAssign<u64> for Instant {
+    #[inline]
+    fn sub_assign(&mut self, rhs: u64) {
+        self.0 = self.0.saturating_sub(rhs)
+    }
+}
+impl PartialOrd for Instant {
+    #[inline]
+    fn partial_cmp(&self, other: &Instant) -> Option<Ordering> {
+        self.0.partial_cmp(&other.0)
+    }
+}
+
 impl Eq for Month {}
 impl Ord for Month {
     #[inline]
@@ -240,21 +412,7 @@ impl Default for Month {
 impl From<u8> for Month {
     #[inline]
     fn from(v: u8) -> Month {
-        match v {
-            0x1 => Month::January,
-            0x2 => Month::February,
-            0x3 => Month::March,
-            0x4 => Month::April,
-            0x5 => Month::May,
-            0x6 => Month::June,
-            0x7 => Month::July,
-            0x8 => Month::August,
-            0x9 => Month::September,
-            0xA => Month::October,
-            0xB => Month::November,
-            0xC => Month::December,
-            _ => Month::None,
-        }
+        month(v)
     }
 }
 impl PartialEq for Month {
@@ -293,16 +451,7 @@ impl Default for Weekday {
 impl From<u8> for Weekday {
     #[inline]
     fn from(v: u8) -> Weekday {
-        match v {
-            0 => Weekday::Sunday,
-            1 => Weekday::Monday,
-            2 => Weekday::Tuesday,
-            3 => Weekday::Wednesday,
-            4 => Weekday::Thursday,
-            5 => Weekday::Friday,
-            6 => Weekday::Saturday,
-            _ => Weekday::None,
-        }
+        weekday(v)
     }
 }
 impl PartialEq for Weekday {
@@ -319,7 +468,38 @@ impl PartialOrd for Weekday {
 }
 
 #[inline]
-fn since_epoch(year: i32) -> i64 {
+const fn month(v: u8) -> Month {
+    match v {
+        0x1 => Month::January,
+        0x2 => Month::February,
+        0x3 => Month::March,
+        0x4 => Month::April,
+        0x5 => Month::May,
+        0x6 => Month::June,
+        0x7 => Month::July,
+        0x8 => Month::August,
+        0x9 => Month::September,
+        0xA => Month::October,
+        0xB => Month::November,
+        0xC => Month::December,
+        _ => Month::None,
+    }
+}
+#[inline]
+const fn weekday(v: u8) -> Weekday {
+    match v {
+        0 => Weekday::Sunday,
+        1 => Weekday::Monday,
+        2 => Weekday::Tuesday,
+        3 => Weekday::Wednesday,
+        4 => Weekday::Thursday,
+        5 => Weekday::Friday,
+        6 => Weekday::Saturday,
+        _ => Weekday::None,
+    }
+}
+#[inline]
+const fn since_epoch(year: i32) -> i64 {
     let mut y = year as i64 + 0x440D116EBF;
     let mut d = 0x23AB1 * (y / 0x190);
     y -= 0x190 * (y / 0x190);
@@ -330,14 +510,18 @@ fn since_epoch(year: i32) -> i64 {
     (d + (0x16D * y)) as i64
 }
 #[inline]
-fn clock(epoch: i64) -> (u8, u8, u8) {
+const fn is_leap(year: u16) -> bool {
+    year % 4 == 0 && (year % 0x64 != 0 || year % 0x190 == 0)
+}
+#[inline]
+const fn clock(epoch: i64) -> (u8, u8, u8) {
     let mut s = epoch % 0x15180;
     let h = s / 0xE10;
     s -= h * 0xE10;
     let m = s / 0x3C;
     (h as u8, m as u8, (s - (m * 0x3C)) as u8)
 }
-fn date(epoch: i64) -> (u16, Month, u8) {
+const fn date(epoch: i64) -> (u16, Month, u8) {
     let mut d = epoch / 0x15180;
     let mut y = 0x190 * (d / 0x23AB1);
     d -= 0x23AB1 * (d / 0x23AB1);
@@ -353,7 +537,7 @@ fn date(epoch: i64) -> (u16, Month, u8) {
     d -= 0x16D * n;
     let v = ((y as i64).wrapping_sub(0x440D116EBF)) as u16;
     let mut k = d as u16;
-    if v % 4 == 0 && (v % 0x64 != 0 || v % 0x190 == 0) {
+    if is_leap(v) {
         if k == 0x3B {
             return (v as u16, Month::February, 29);
         } else if k > 0x3B {
@@ -361,17 +545,13 @@ fn date(epoch: i64) -> (u16, Month, u8) {
         }
     }
     let m = (k / 0x1F) as usize;
-    let e = unsafe { *DAYS_IN_YEAR.get_unchecked((m + 1).min(12)) };
+    let e = DAYS_IN_YEAR[if m >= 12 { 12 } else { m + 1 }];
     if k >= e {
-        return (v, Month::from((m + 2) as u8), (k - e + 1) as u8);
+        return (v, month((m + 2) as u8), (k - e + 1) as u8);
     }
-    (
-        v,
-        Month::from((m + 1) as u8),
-        (k - unsafe { *DAYS_IN_YEAR.get_unchecked(m.min(12)) } + 1) as u8,
-    )
+    (v, month((m + 1) as u8), (k - DAYS_IN_YEAR[if m > 12 { 12 } else { m }] + 1) as u8)
 }
-fn norm(hi: i32, low: i32, base: i32) -> (i32, i32) {
+const fn norm(hi: i32, low: i32, base: i32) -> (i32, i32) {
     let (mut x, mut y) = (hi, low);
     if y < 0 {
         let n = (-y - 1) / base + 1;
@@ -386,13 +566,98 @@ fn norm(hi: i32, low: i32, base: i32) -> (i32, i32) {
     (x, y)
 }
 
+const _: () = {
+    const fn same(a: &Time, b: &Time) -> bool {
+        a.day == b.day
+            && a.year == b.year
+            && a.mins == b.mins
+            && a.secs == b.secs
+            && a.hours == b.hours
+            && a.month as u8 == b.month as u8
+    }
+    let t = [
+        (0i64, Time::new(1970, Month::January, 1, 0, 0, 0, Weekday::Thursday)),
+        (951_782_400i64, Time::new(2000, Month::February, 29, 0, 0, 0, Weekday::Tuesday)),
+        (2_147_483_647i64, Time::new(2038, Month::January, 19, 3, 14, 7, Weekday::Tuesday)),
+        (4_107_456_000i64, Time::new(2100, Month::February, 28, 0, 0, 0, Weekday::Sunday)),
+        (UNIX_MIN, Time::new(0, Month::January, 1, 0, 0, 0, Weekday::Saturday)),
+        (UNIX_MAX, Time::new(YEAR_MAX, Month::December, 31, 23, 59, 59, Weekday::Sunday)),
+    ];
+    let mut i = 0usize;
+    while i < t.len() {
+        let r = Time::from_unix(t[i].0);
+        assert!(r.is_valid() && same(&r, &t[i].1));
+        assert!(r.weekday as u8 == t[i].1.weekday as u8);
+        i += 1;
+    }
+    // Anything outside of the RTC range is clamped.
+    assert!(same(&Time::from_unix(i64::MAX), &Time::from_unix(UNIX_MAX)));
+    assert!(same(&Time::from_unix(UNIX_MAX + 1), &Time::from_unix(UNIX_MAX)));
+    assert!(same(&Time::from_unix(i64::MIN), &Time::from_unix(UNIX_MIN)));
+    assert!(same(&Time::from_unix(UNIX_MIN - 1), &Time::from_unix(UNIX_MIN)));
+    // And going back saturates.
+    let v = Time::new(3000, Month::January, 1, 0, 0, 0, Weekday::None);
+    assert!(!v.is_valid() && v.into_unix() > UNIX_MAX);
+    let t = [
+        Time::new(1970, Month::January, 1, 0, 0, 0, Weekday::None),
+        Time::new(1969, Month::December, 31, 23, 59, 59, Weekday::None),
+        Time::new(1900, Month::February, 28, 12, 0, 0, Weekday::None),
+        Time::new(2000, Month::February, 29, 1, 2, 3, Weekday::None),
+        Time::new(2000, Month::March, 1, 0, 0, 0, Weekday::None),
+        Time::new(2024, Month::February, 29, 23, 59, 59, Weekday::None),
+        Time::new(2024, Month::December, 31, 0, 0, 0, Weekday::None),
+        Time::new(2038, Month::January, 19, 3, 14, 8, Weekday::None),
+        Time::new(2100, Month::February, 28, 0, 0, 0, Weekday::None),
+        Time::new(2100, Month::March, 1, 0, 0, 0, Weekday::None),
+        Time::new(0, Month::February, 29, 0, 0, 0, Weekday::None),
+    ];
+    let mut i = 0usize;
+    while i < t.len() {
+        assert!(same(&Time::from_unix(t[i].into_unix()), &t[i]));
+        i += 1;
+    }
+};
+const _: () = {
+    let a = Time::new(1970, Month::January, 1, 0, 0, 0, Weekday::None);
+    let b = Time::new(2100, Month::February, 28, 0, 0, 0, Weekday::None);
+    assert!(Weekday::from_time(&a) as u8 == Weekday::Thursday as u8);
+    assert!(Weekday::from_time(&b) as u8 == Weekday::Sunday as u8);
+    assert!(Time::from_seconds(a.into_seconds()).weekday as u8 == Weekday::Thursday as u8);
+    assert!(Time::from_seconds(b.into_seconds()).weekday as u8 == Weekday::Sunday as u8);
+    let t = [
+        (Time::new(2023, Month::January, 1, 0, 0, 0, Weekday::None), 1u16),
+        (Time::new(2023, Month::March, 1, 0, 0, 0, Weekday::None), 60u16),
+        (Time::new(2023, Month::December, 31, 0, 0, 0, Weekday::None), 365u16),
+        (Time::new(2024, Month::February, 29, 0, 0, 0, Weekday::None), 60u16),
+        (Time::new(2024, Month::March, 1, 0, 0, 0, Weekday::None), 61u16),
+        (Time::new(2024, Month::December, 31, 0, 0, 0, Weekday::None), 366u16),
+        (Time::new(2100, Month::March, 1, 0, 0, 0, Weekday::None), 60u16),
+    ];
+    let mut i = 0usize;
+    while i < t.len() {
+        assert!(t[i].0.day_of_year() == t[i].1);
+        i += 1;
+    }
+    // 2024-01-01 is a Monday, 2024-01-07 is a Sunday.
+    assert!(Time::new(2024, Month::January, 1, 0, 0, 0, Weekday::None).iso_weekday() == 1);
+    assert!(Time::new(2024, Month::January, 7, 0, 0, 0, Weekday::None).iso_weekday() == 7);
+    assert!(Time::new(2024, Month::January, 6, 0, 0, 0, Weekday::None).iso_weekday() == 6);
+};
+
 #[cfg(feature = "debug")]
 mod display {
     extern crate core;
 
     use core::fmt::{Debug, Display, Formatter, Result, Write};
 
-    use crate::time::{Month, Time, Weekday};
+    use crate::time::{Instant, Month, Time, Weekday};
+
+    impl Debug for Instant {
+        #[inline]
+        fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+            f.write_fmt(format_args!("{}us", self.0))
+        }
+    }
 
     impl Debug for Time {
         #[inline]
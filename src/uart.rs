@@ -21,21 +21,26 @@
 
 extern crate core;
 
+use core::assert;
+use core::cmp::Ord;
 use core::convert::TryFrom;
 use core::default::Default;
 use core::fmt::{self, Debug, Formatter, Write};
 use core::hint::unreachable_unchecked;
-use core::marker::Send;
+use core::marker::{PhantomData, Send};
+use core::ops::Drop;
 use core::option::Option::{self, None, Some};
 use core::ptr::NonNull;
 use core::result::Result::{self, Err, Ok};
 
 use crate::Board;
 use crate::asm::nop;
+use crate::clock::Timer;
 use crate::dma::{DmaReader, DmaWriter};
+use crate::int::Acknowledge;
 use crate::pac::uart0::RegisterBlock;
 use crate::pac::{RESETS, UART0, UART1};
-use crate::pin::{PinFunction, PinID, UartID, pins_uart};
+use crate::pin::{PinFunction, PinID, UartID, pins_uart, valid_uart};
 
 #[repr(u8)]
 pub enum UartBits {
@@ -52,6 +57,13 @@ pub enum UartError {
     ReadInvalid,
     WouldBlock,
 }
+pub enum UartEvent {
+    Data(u8),
+    Break,
+    Parity(u8),
+    Overrun(u8),
+    Framing(u8),
+}
 pub enum UartParity {
     None,
     Odd,
@@ -73,6 +85,10 @@ pub enum UartWatermark {
 pub struct Uart {
     dev: NonNull<RegisterBlock>,
 }
+pub struct UartRxIdle<'a> {
+    dev: NonNull<RegisterBlock>,
+    _p:  PhantomData<&'a Uart>,
+}
 pub struct UartDev {
     pub tx:  PinID,
     pub rx:  PinID,
@@ -87,7 +103,7 @@ pub struct UartConfig {
 
 impl Uart {
     pub fn new(p: &Board, baudrate: u32, cfg: UartConfig, d: UartDev) -> Result<Uart, UartError> {
-        let (i, f) = calc_dvs(baudrate, p.system_freq())?;
+        let (i, f) = calc_dvs(baudrate, p.peripheral_freq())?;
         let v = d.device().ok_or(UartError::InvalidPins)?;
         unsafe {
             let t = &*v;
@@ -97,9 +113,9 @@ impl Uart {
             t.uartlcr_h().write(|r| {
                 r.fen().set_bit();
                 match cfg.parity {
-                    UartParity::None => r.pen().bit(false),
-                    UartParity::Odd => r.eps().clear_bit(),
-                    UartParity::Even => r.eps().set_bit(),
+                    UartParity::None => r.pen().clear_bit(),
+                    UartParity::Odd => r.pen().set_bit().eps().clear_bit(),
+                    UartParity::Even => r.pen().set_bit().eps().set_bit(),
                 };
                 r.wlen().bits(cfg.data_bits as u8).stp2().bit((cfg.stop_bits as u8) == 1)
             });
@@ -179,6 +195,18 @@ impl Uart {
     pub fn set_rx_interrupt(&mut self, en: bool) {
         self.ptr().uartimsc().modify(|_, r| r.rxim().bit(en).rtim().bit(en))
     }
+    #[inline]
+    pub fn rx_idle(&self) -> UartRxIdle<'_> {
+        UartRxIdle { dev: self.dev, _p: PhantomData }
+    }
+    #[inline]
+    pub fn set_rx_timeout_interrupt(&mut self, en: bool) {
+        // NOTE(sf): The receive timeout fires when the RX FIFO is not empty and
+        //           no new data has arrived for 32 bit periods (about 3 frames
+        //           at 8N1). It is cleared by reading the FIFO empty or by the
+        //           ack on 'UartRxIdle'.
+        self.ptr().uartimsc().modify(|_, r| r.rtim().bit(en))
+    }
     pub fn write_full(&mut self, b: &[u8]) -> usize {
         let mut n = 0;
         while n < b.len() {
@@ -201,6 +229,16 @@ impl Uart {
             .modify(|_, r| unsafe { r.txiflsel().bits(w.bits_tx()) })
     }
     #[inline]
+    pub fn configure_dma(&mut self, rx: bool, tx: bool, rx_watermark: UartWatermark) {
+        // NOTE(sf): 'new' enables both DREQs, leaving TX on when only RX uses
+        //           DMA keeps the TX DREQ asserted for nothing. The RX FIFO
+        //           level also sets when the DMA gets a burst request.
+        if rx {
+            self.set_rx_watermark(rx_watermark);
+        }
+        self.ptr().uartdmacr().write(|r| r.txdmae().bit(tx).rxdmae().bit(rx))
+    }
+    #[inline]
     pub fn set_rx_watermark(&mut self, w: UartWatermark) {
         self.ptr()
             .uartifls()
@@ -238,6 +276,69 @@ impl Uart {
         }
         Ok(n)
     }
+    // NOTE(sf): 'peri_freq' is the peripheral clock (clk_peri) that feeds the
+    //           UART, see 'Board::peripheral_freq'.
+    pub fn set_baudrate(&mut self, baud: u32, peri_freq: u32) -> Result<(), UartError> {
+        let (i, f) = calc_dvs(baud, peri_freq)?;
+        while self.is_busy() {
+            nop();
+        }
+        let p = self.ptr();
+        let c = p.uartcr().read().bits();
+        p.uartcr().modify(|_, r| r.uarten().clear_bit());
+        p.uartibrd().write(|r| unsafe { r.baud_divint().bits(i) });
+        p.uartfbrd().write(|r| unsafe { r.baud_divfrac().bits(f as u8) });
+        // NOTE(sf): The divisors are only latched on a write to LCR_H.
+        p.uartlcr_h().modify(|_, r| r);
+        p.uartcr().write(|r| unsafe { r.bits(c) });
+        Ok(())
+    }
+    pub fn set_format(&mut self, bits: UartBits, parity: UartParity, stop: UartStopBits) {
+        while self.is_busy() {
+            nop();
+        }
+        let p = self.ptr();
+        let c = p.uartcr().read().bits();
+        p.uartcr().modify(|_, r| r.uarten().clear_bit());
+        p.uartlcr_h().modify(|_, r| {
+            match parity {
+                UartParity::None => r.pen().clear_bit(),
+                UartParity::Odd => r.pen().set_bit().eps().clear_bit(),
+                UartParity::Even => r.pen().set_bit().eps().set_bit(),
+            };
+            unsafe { r.wlen().bits(bits as u8) }.stp2().bit((stop as u8) == 1)
+        });
+        p.uartcr().write(|r| unsafe { r.bits(c) });
+    }
+    pub fn send_break(&mut self, p: &Board, duration_bits: u16) {
+        while self.is_busy() {
+            nop();
+        }
+        let r = self.ptr();
+        // NOTE(sf): The UART samples at 16x the baud rate, so one bit period is
+        //           16 * (IBRD + FBRD / 64) peripheral clocks. clk_peri isn't
+        //           always clk_sys, so convert to microseconds (rounding up)
+        //           and sleep instead of spinning.
+        let d = (r.uartibrd().read().bits() as u64 * 64 + r.uartfbrd().read().bits() as u64) / 4;
+        let n = (d * duration_bits as u64 * 1_000_000).div_ceil(p.peripheral_freq().max(1) as u64);
+        r.uartlcr_h().modify(|_, r| r.brk().set_bit());
+        p.sleep_us(n.min(u32::MAX as u64) as u32);
+        r.uartlcr_h().modify(|_, r| r.brk().clear_bit());
+    }
+    pub fn read_event(&mut self) -> Option<UartEvent> {
+        if !self.is_readable() {
+            return None;
+        }
+        let v = self.ptr().uartdr().read();
+        let d = v.data().bits();
+        Some(match v {
+            _ if v.be().bit_is_set() => UartEvent::Break,
+            _ if v.oe().bit_is_set() => UartEvent::Overrun(d),
+            _ if v.fe().bit_is_set() => UartEvent::Framing(d),
+            _ if v.pe().bit_is_set() => UartEvent::Parity(d),
+            _ => UartEvent::Data(d),
+        })
+    }
     pub fn read_full(&mut self, b: &mut [u8]) -> Result<usize, UartError> {
         let mut n = 0;
         while n < b.len() {
@@ -249,13 +350,44 @@ impl Uart {
         }
         Ok(n)
     }
+    pub fn read_timeout(&mut self, b: &mut [u8], timer: &Timer, us: u32) -> Result<usize, UartError> {
+        let (mut n, d) = (0, timer.current_tick() + us as u64);
+        while n < b.len() {
+            n += match self.read(unsafe { b.get_unchecked_mut(n..) }) {
+                Ok(n) => n,
+                Err(UartError::WouldBlock) if timer.current_tick() >= d => break,
+                Err(UartError::WouldBlock) => continue,
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(n)
+    }
 
     #[inline]
     fn ptr(&self) -> &RegisterBlock {
         unsafe { self.dev.as_ref() }
     }
 }
+impl UartRxIdle<'_> {
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        unsafe { self.dev.as_ref() }.uartmis().read().rtmis().bit_is_set()
+    }
+    #[inline]
+    pub fn interrupt_clear(&mut self) {
+        unsafe { self.dev.as_ref() }.uarticr().write(|r| r.rtic().clear_bit_by_one())
+    }
+}
 impl UartDev {
+    #[inline]
+    pub const fn checked(tx: PinID, rx: PinID, cts: Option<PinID>, rts: Option<PinID>) -> UartDev {
+        // NOTE(sf): This is only a compile error when evaluated in a const
+        //           context, ie: 'const D: UartDev = UartDev::checked(..);'.
+        //           At runtime it just panics, use 'new' to get an error.
+        assert!(valid_uart(tx, rx, cts, rts), "invalid UART pins");
+        UartDev { tx, rx, cts, rts }
+    }
+
     #[inline]
     pub fn new(tx: PinID, rx: PinID) -> Result<UartDev, UartError> {
         let d = UartDev { tx, rx, cts: None, rts: None };
@@ -416,6 +548,27 @@ impl DmaWriter<u8> for Uart {
     }
 }
 
+impl Drop for Uart {
+    #[inline]
+    fn drop(&mut self) {
+        let r = unsafe { RESETS::steal() };
+        if self.dev.as_ptr().addr() == UART0::PTR.addr() {
+            r.reset().modify(|_, r| r.uart0().set_bit());
+        } else {
+            r.reset().modify(|_, r| r.uart1().set_bit());
+        }
+    }
+}
+
+impl Acknowledge for UartRxIdle<'_> {
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        let r = self.is_idle();
+        self.interrupt_clear();
+        r
+    }
+}
+
 unsafe impl Send for Uart {}
 
 impl Debug for UartError {
@@ -450,3 +603,78 @@ fn calc_dvs(w: u32, f: u32) -> Result<(u16, u16), UartError> {
         (x, y) => Ok((x as u16, y as u16)),
     }
 }
+
+#[cfg(feature = "embedded-io")]
+mod io {
+    extern crate core;
+    extern crate embedded_io;
+
+    use core::result::Result::{self, Err, Ok};
+
+    use self::embedded_io::{Error, ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+    use crate::asm::nop;
+    use crate::uart::{Uart, UartError};
+
+    impl Error for UartError {
+        #[inline]
+        fn kind(&self) -> ErrorKind {
+            match self {
+                UartError::ReadInvalid => ErrorKind::InvalidData,
+                UartError::WouldBlock | UartError::ReadBreak | UartError::ReadOverrun => ErrorKind::Other,
+                UartError::InvalidPins | UartError::InvalidBaudRate => ErrorKind::InvalidInput,
+            }
+        }
+    }
+
+    impl ErrorType for Uart {
+        type Error = UartError;
+    }
+
+    impl Read for Uart {
+        #[inline]
+        fn read(&mut self, b: &mut [u8]) -> Result<usize, UartError> {
+            if b.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                match Uart::read(self, b) {
+                    Err(UartError::WouldBlock) => nop(),
+                    r => return r,
+                }
+            }
+        }
+    }
+    impl Write for Uart {
+        #[inline]
+        fn flush(&mut self) -> Result<(), UartError> {
+            while let Err(UartError::WouldBlock) = Uart::flush(self) {
+                nop();
+            }
+            Ok(())
+        }
+        #[inline]
+        fn write(&mut self, b: &[u8]) -> Result<usize, UartError> {
+            if b.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                match Uart::write(self, b) {
+                    Err(UartError::WouldBlock) => nop(),
+                    r => return r,
+                }
+            }
+        }
+    }
+    impl ReadReady for Uart {
+        #[inline]
+        fn read_ready(&mut self) -> Result<bool, UartError> {
+            Ok(self.is_readable())
+        }
+    }
+    impl WriteReady for Uart {
+        #[inline]
+        fn write_ready(&mut self) -> Result<bool, UartError> {
+            Ok(self.is_writable())
+        }
+    }
+}
@@ -22,9 +22,22 @@
 extern crate core;
 
 use core::cell::UnsafeCell;
+use core::clone::Clone;
 use core::cmp::Ord;
+use core::marker::Copy;
+use core::mem::transmute;
 
-use crate::pac::{PSM, WATCHDOG};
+use crate::asm::nop;
+use crate::flash::rom_func;
+use crate::pac::{PSM, VREG_AND_CHIP_RESET, WATCHDOG};
+
+pub enum ResetReason {
+    PowerOn,
+    RunPin,
+    Debug,
+    Watchdog,
+    Software,
+}
 
 pub enum Scratch {
     Register0,
@@ -43,6 +56,11 @@ pub struct Watchdog {
 }
 
 impl Watchdog {
+    // NOTE(sf): The countdown is 24 bits, but due to RP2040-E1 the counter
+    //           decrements twice per tick, so the load value is doubled and
+    //           the max timeout is halved (~8.3s).
+    pub const MAX_TIMEOUT_US: u32 = 0x7FFFFFu32;
+
     #[inline]
     pub(crate) fn new(freq: u32) -> Watchdog {
         let w = unsafe { WATCHDOG::steal() };
@@ -63,7 +81,7 @@ impl Watchdog {
     }
     #[inline]
     pub fn disable(&self) {
-        self.dog.ctrl().write(|r| r.enable().clear_bit());
+        self.dog.ctrl().modify(|_, r| r.enable().clear_bit());
     }
     #[inline]
     pub fn enable_ticks(&self) {
@@ -71,7 +89,7 @@ impl Watchdog {
     }
     #[inline]
     pub fn start(&self, ms: u32) {
-        self.start_us(ms * 1_000);
+        self.start_us(ms.saturating_mul(1_000));
     }
     #[inline]
     pub fn countdown(&self) -> u16 {
@@ -79,11 +97,11 @@ impl Watchdog {
     }
     #[inline]
     pub fn restart(&self, ms: u32) {
-        self.restart_us(ms * 1_000)
+        self.restart_us(ms.saturating_mul(1_000))
     }
     pub fn start_us(&self, us: u32) {
-        let d = us.min(0x7FFFFF);
-        self.dog.ctrl().write(|r| r.enable().clear_bit());
+        let d = us.min(Watchdog::MAX_TIMEOUT_US);
+        self.dog.ctrl().modify(|_, r| r.enable().clear_bit());
         unsafe {
             PSM::steal()
                 .wdsel()
@@ -94,7 +112,11 @@ impl Watchdog {
             self.enable_ticks();
         }
         self.feed();
-        self.dog.ctrl().write(|r| r.enable().set_bit())
+        self.dog.ctrl().modify(|_, r| r.enable().set_bit())
+    }
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.dog.ctrl().read().enable().bit_is_set()
     }
     #[inline]
     pub fn is_ticking(&self) -> bool {
@@ -107,7 +129,7 @@ impl Watchdog {
     }
     #[inline]
     pub fn pause_on_debug(&self, pause_en: bool) {
-        self.dog.ctrl().write(|r| {
+        self.dog.ctrl().modify(|_, r| {
             r.pause_dbg0()
                 .bit(pause_en)
                 .pause_dbg1()
@@ -147,3 +169,45 @@ impl Watchdog {
         }
     }
 }
+
+impl Copy for ResetReason {}
+impl Clone for ResetReason {
+    #[inline]
+    fn clone(&self) -> ResetReason {
+        *self
+    }
+}
+
+pub fn reboot_to_bootloader() -> ! {
+    // NOTE(sf): The RP2040 bootrom doesn't check the scratch registers for a
+    //           BOOTSEL request (only for a vector to jump to), so we have to
+    //           go through the ROM 'reset_usb_boot' function, which stashes
+    //           it's own magic in the watchdog scratch registers and resets.
+    let p = rom_func(b'U', b'B');
+    if p != 0 {
+        let b: extern "C" fn(u32, u32) = unsafe { transmute(p) };
+        b(0, 0);
+    }
+    // NOTE(sf): Shouldn't get here, but if the lookup failed, just do a normal
+    //           reset instead.
+    unsafe { WATCHDOG::steal() }.ctrl().write(|r| r.trigger().set_bit());
+    loop {
+        nop();
+    }
+}
+#[inline]
+pub fn last_reset_reason() -> ResetReason {
+    let r = unsafe { WATCHDOG::steal() }.reason().read();
+    if r.force().bit_is_set() {
+        return ResetReason::Software;
+    }
+    if r.timer().bit_is_set() {
+        return ResetReason::Watchdog;
+    }
+    let c = unsafe { VREG_AND_CHIP_RESET::steal() }.chip_reset().read();
+    match c {
+        _ if c.had_psm_restart().bit_is_set() => ResetReason::Debug,
+        _ if c.had_run().bit_is_set() => ResetReason::RunPin,
+        _ => ResetReason::PowerOn,
+    }
+}